@@ -0,0 +1,397 @@
+//! Speaks the Universal Chess Interface over stdin and stdout, so that the engine can be used
+//! from GUIs and match runners.
+
+extern crate ajaccio;
+
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use ajaccio::clock::{TimeBudget, TimeControl};
+use ajaccio::motion::Move;
+use ajaccio::pgn::STARTING_FEN;
+use ajaccio::position::{Color, Position};
+use ajaccio::search::{self, SearchInfo, SearchLimits};
+use ajaccio::transposition::TranspositionTable;
+
+/// The size of the transposition table, in megabytes, until the GUI asks for something else.
+const DEFAULT_HASH: usize = 16;
+const MAX_HASH: usize = 65_536;
+
+/// How long to allow, in milliseconds, for the GUI to deal with a move and press the clock.
+const DEFAULT_MOVE_OVERHEAD: u64 = 10;
+const MAX_MOVE_OVERHEAD: u64 = 5_000;
+
+/// The limits a `go` command puts on a search. Anything left as `None` is unlimited.
+#[derive(Clone, Debug, Default)]
+struct Limits {
+    depth: Option<u32>,
+    nodes: Option<u64>,
+    movetime: Option<Duration>,
+    wtime: Option<Duration>,
+    btime: Option<Duration>,
+    winc: Option<Duration>,
+    binc: Option<Duration>,
+    movestogo: Option<u32>,
+    /// Search until told to stop, and don't report a move before then.
+    infinite: bool
+}
+
+/// A search running in the background, which `stop` can cut short.
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>
+}
+
+struct Engine {
+    position: Position,
+    /// The hashes of the positions played before `position`, oldest first.
+    history: Vec<u64>,
+    table: Arc<TranspositionTable>,
+    move_overhead: Duration,
+    search: Option<Search>
+}
+
+impl Engine {
+    fn new() -> Engine {
+        Engine {
+            position: Position::from_fen(STARTING_FEN).unwrap(),
+            history: vec![],
+            table: Arc::new(TranspositionTable::new(DEFAULT_HASH)),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            search: None
+        }
+    }
+
+    /// Handles one line of input. Returns false once it's time to quit.
+    fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("uci") => {
+                println!("id name Ajaccio");
+                println!("id author Ulysse Carion");
+                println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH,
+                         MAX_HASH);
+                println!("option name Move Overhead type spin default {} min 0 max {}",
+                         DEFAULT_MOVE_OVERHEAD, MAX_MOVE_OVERHEAD);
+                println!("uciok");
+            },
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.stop();
+                self.position = Position::from_fen(STARTING_FEN).unwrap();
+                self.history.clear();
+                self.table.clear();
+            },
+            Some("position") => {
+                self.stop();
+
+                if let Err(message) = self.set_position(&tokens.collect::<Vec<_>>()) {
+                    println!("info string {}", message);
+                }
+            },
+            Some("go") => {
+                self.stop();
+
+                match parse_limits(&tokens.collect::<Vec<_>>()) {
+                    Ok(limits) => self.go(limits),
+                    Err(message) => println!("info string {}", message)
+                }
+            },
+            Some("stop") => self.stop(),
+            Some("setoption") => {
+                self.stop();
+
+                if let Err(message) = self.set_option(&tokens.collect::<Vec<_>>()) {
+                    println!("info string {}", message);
+                }
+            },
+            Some("quit") => {
+                self.stop();
+                return false;
+            },
+            // the protocol says to ignore anything else
+            _ => {}
+        }
+
+        true
+    }
+
+    fn set_position(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let (fen, rest) = match tokens.split_first() {
+            Some((&"startpos", rest)) => (STARTING_FEN.to_string(), rest),
+            Some((&"fen", rest)) => {
+                let fields = rest.iter().take_while(|&&token| token != "moves").count();
+                (rest[..fields].join(" "), &rest[fields..])
+            },
+            _ => return Err("expected \"startpos\" or \"fen\"".to_string())
+        };
+
+        let mut position = Position::from_fen(&fen).map_err(|error| error.to_string())?;
+        let mut history = vec![];
+
+        if let Some((&"moves", moves)) = rest.split_first() {
+            for uci in moves {
+                let motion = position.parse_uci(uci).map_err(|error| error.to_string())?;
+                history.push(position.hash);
+                position.make_move(motion);
+            }
+        }
+
+        self.position = position;
+        self.history = history;
+        Ok(())
+    }
+
+    fn set_option(&mut self, tokens: &[&str]) -> Result<(), String> {
+        // names and values can both have spaces in them
+        let value_at = tokens.iter().position(|&token| token == "value");
+        let name = tokens[..value_at.unwrap_or(tokens.len())].iter()
+            .skip_while(|&&token| token == "name")
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let value = value_at.map(|index| tokens[index + 1..].join(" "));
+
+        match (name.to_lowercase().as_str(), value) {
+            ("hash", Some(value)) => {
+                let megabytes = value.parse::<usize>()
+                    .map_err(|_| format!("bad value for Hash: {:?}", value))?;
+                self.table = Arc::new(TranspositionTable::new(megabytes.clamp(1, MAX_HASH)));
+                Ok(())
+            },
+            ("move overhead", Some(value)) => {
+                let millis = value.parse::<u64>()
+                    .map_err(|_| format!("bad value for Move Overhead: {:?}", value))?;
+                self.move_overhead = Duration::from_millis(millis.min(MAX_MOVE_OVERHEAD));
+                Ok(())
+            },
+            _ => Err(format!("no such option: {}", name))
+        }
+    }
+
+    fn go(&mut self, limits: Limits) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let position = self.position.clone();
+        let history = self.history.clone();
+        let table = self.table.clone();
+        let time = time_budget(position.side_to_play, &limits, self.move_overhead);
+        let thread_stop = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let best = think(&position, &history, &limits, time, &table, &thread_stop);
+
+            match best {
+                Some(motion) => println!("bestmove {}", motion),
+                None => println!("bestmove 0000")
+            }
+        });
+
+        self.search = Some(Search { stop, handle });
+    }
+
+    /// Stops the search, if there is one, and waits for it to report its move.
+    fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::SeqCst);
+            search.handle.join().unwrap();
+        }
+    }
+}
+
+/// Searches for a move to play, reporting on each iteration as it goes.
+fn think(position: &Position, history: &[u64], limits: &Limits, time: Option<TimeBudget>,
+         table: &TranspositionTable, stop: &AtomicBool) -> Option<Move> {
+    let search_limits = SearchLimits {
+        depth: limits.depth,
+        nodes: limits.nodes,
+        time
+    };
+
+    let best = search::search(position, history, search_limits, table, stop, print_info)
+        .best_move();
+
+    // an infinite search mustn't report its move until it's told to stop
+    while limits.infinite && !stop.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    best
+}
+
+/// How long to spend on this move, from whichever side's clock is ticking. Infinite searches
+/// ignore the clock.
+fn time_budget(side: Color, limits: &Limits, overhead: Duration) -> Option<TimeBudget> {
+    if limits.infinite {
+        return None;
+    }
+
+    let (remaining, increment) = match side {
+        Color::White => (limits.wtime, limits.winc),
+        Color::Black => (limits.btime, limits.binc)
+    };
+
+    let control = TimeControl {
+        remaining,
+        increment: increment.unwrap_or_default(),
+        moves_to_go: limits.movestogo,
+        move_time: limits.movetime,
+        overhead
+    };
+
+    control.budget()
+}
+
+fn print_info(info: &SearchInfo) {
+    let millis = info.time.as_millis().max(1);
+    let pv: Vec<_> = info.pv.iter().map(Move::to_uci).collect();
+
+    println!("info depth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+             info.depth, info.score, info.nodes, info.nodes as u128 * 1000 / millis,
+             info.hashfull, info.time.as_millis(), pv.join(" "));
+}
+
+fn parse_limits(tokens: &[&str]) -> Result<Limits, String> {
+    let mut limits = Limits::default();
+    let mut tokens = tokens.iter();
+
+    while let Some(&token) = tokens.next() {
+        if token == "infinite" {
+            limits.infinite = true;
+            continue;
+        }
+
+        // anything else we don't understand, like the moves after "searchmoves", is skipped
+        let parameters = ["depth", "nodes", "movetime", "wtime", "btime", "winc", "binc",
+                          "movestogo"];
+        if !parameters.contains(&token) {
+            continue;
+        }
+
+        let value = match tokens.next() {
+            Some(value) => value.parse::<i64>()
+                .map_err(|_| format!("bad value for {}: {:?}", token, value))?,
+            None => return Err(format!("missing value for {}", token))
+        };
+
+        // clocks can go negative when a GUI is late to account for lag
+        let millis = Some(Duration::from_millis(value.max(0) as u64));
+        match token {
+            "depth" => limits.depth = Some(value.max(0) as u32),
+            "nodes" => limits.nodes = Some(value.max(0) as u64),
+            "movetime" => limits.movetime = millis,
+            "wtime" => limits.wtime = millis,
+            "btime" => limits.btime = millis,
+            "winc" => limits.winc = millis,
+            "binc" => limits.binc = millis,
+            _ => limits.movestogo = Some(value.max(0) as u32)
+        }
+    }
+
+    Ok(limits)
+}
+
+fn main() {
+    let mut engine = Engine::new();
+    let stdin = io::stdin();
+
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) => {
+                if !engine.handle(&line) {
+                    return;
+                }
+            },
+            Err(_) => break
+        }
+    }
+
+    engine.stop();
+}
+
+#[test]
+fn go_parameters() {
+    let limits = parse_limits(&["wtime", "-20", "btime", "3000", "searchmoves", "e2e4",
+                                "movestogo", "12"]).unwrap();
+    assert_eq!(Some(Duration::from_millis(0)), limits.wtime);
+    assert_eq!(Some(Duration::from_millis(3000)), limits.btime);
+    assert_eq!(Some(12), limits.movestogo);
+    assert!(!limits.infinite);
+
+    assert!(parse_limits(&["infinite"]).unwrap().infinite);
+
+    // black's clock is the one that counts when it's black's move
+    let overhead = Duration::from_millis(0);
+    assert_eq!(Duration::from_millis(250),
+               time_budget(Color::Black, &limits, overhead).unwrap().soft);
+    assert_eq!(None, time_budget(Color::White, &parse_limits(&["infinite", "wtime", "10"])
+                                     .unwrap(), overhead));
+    assert!(parse_limits(&["depth"]).is_err());
+    assert!(parse_limits(&["nodes", "lots"]).is_err());
+}
+
+#[test]
+fn position_command() {
+    let mut engine = Engine::new();
+
+    engine.handle("position startpos moves e2e4 e7e5 g1f3");
+    assert_eq!("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+               engine.position.to_fen());
+
+    engine.handle("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1");
+    assert_eq!("4k3/8/8/8/8/8/8/5RK1 b - - 1 1", engine.position.to_fen());
+
+    // a bad move leaves the position alone
+    engine.handle("position startpos moves e2e5");
+    assert_eq!("4k3/8/8/8/8/8/8/5RK1 b - - 1 1", engine.position.to_fen());
+}
+
+#[test]
+fn hash_option() {
+    let mut engine = Engine::new();
+    let table = engine.table.clone();
+
+    assert!(engine.set_option(&["name", "Hash", "value", "lots"]).is_err());
+    assert!(engine.set_option(&["name", "Threads", "value", "4"]).is_err());
+    assert!(Arc::ptr_eq(&table, &engine.table));
+
+    engine.set_option(&["name", "Hash", "value", "1"]).unwrap();
+    assert!(!Arc::ptr_eq(&table, &engine.table));
+
+    engine.set_option(&["name", "Move", "Overhead", "value", "250"]).unwrap();
+    assert_eq!(Duration::from_millis(250), engine.move_overhead);
+}
+
+#[test]
+fn repetitions() {
+    use ajaccio::search::Score;
+
+    let mut engine = Engine::new();
+
+    // the knights going out and back again repeat the starting position
+    engine.handle("position startpos moves g1f3 g8f6 f3g1 f6g8");
+    assert_eq!(4, engine.history.len());
+    assert_eq!(engine.position.hash, engine.history[0]);
+
+    // black's king can only shuffle between a8 and b8, and going back to b8 repeats the
+    // position after its first move, which saves it from being a rook down
+    engine.handle("position fen k7/p7/P7/8/8/8/8/2R4K b - - 0 1 moves a8b8 h1h2 b8a8 h2h1");
+    let limits = SearchLimits {
+        depth: Some(3),
+        ..SearchLimits::default()
+    };
+    let stop = AtomicBool::new(false);
+
+    let info = search::search(&engine.position, &engine.history, limits, &engine.table, &stop,
+                              |_| {});
+    assert_eq!("a8b8", info.best_move().unwrap().to_uci());
+    assert_eq!(Score::Centipawns(0), info.score);
+
+    // without the game's moves, it looks like black is just lost
+    let table = TranspositionTable::new(1);
+    let info = search::search(&engine.position, &[], limits, &table, &stop, |_| {});
+    assert!(info.score != Score::Centipawns(0));
+}
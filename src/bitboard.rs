@@ -53,7 +53,7 @@ impl Bitboard {
     }
 
     pub fn is_occupied(&self, square: Square) -> bool {
-        (self.clone() & square.to_bitboard()).is_nonempty()
+        (*self & square.to_bitboard()).is_nonempty()
     }
 
     pub fn num_occupied_squares(&self) -> u32 {
@@ -61,17 +61,17 @@ impl Bitboard {
     }
 
     pub fn squares(self) -> SquaresIter {
-        SquaresIter { bitboard: self }
+        SquaresIter::new(self)
     }
 }
 
 impl fmt::Display for Bitboard {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let line = "+---+---+---+---+---+---+---+---+\n";
-        try!(write!(f, "{}", line));
+        write!(f, "{}", line)?;
 
         for rank in (0..8).rev() {
-            try!(write!(f, "|"));
+            write!(f, "|")?;
 
             for file in 0..8 {
                 let sq = Square::from_coords(file, rank);
@@ -81,11 +81,11 @@ impl fmt::Display for Bitboard {
                     ' '
                 };
 
-                try!(write!(f, " {} |", to_write));
+                write!(f, " {} |", to_write)?;
             }
 
-            try!(write!(f, "\n"));
-            try!(write!(f, "{}", line));
+            writeln!(f)?;
+            write!(f, "{}", line)?;
         }
 
         Ok(())
@@ -114,7 +114,7 @@ impl Iterator for SquaresIter {
 
 impl SquaresIter {
     fn new(bitboard: Bitboard) -> SquaresIter {
-        SquaresIter { bitboard: bitboard }
+        SquaresIter { bitboard }
     }
 }
 
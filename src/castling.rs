@@ -0,0 +1,133 @@
+use std::ops::{BitAnd, BitOr, Not};
+use std::fmt;
+
+use motion::CastlingType;
+use position::Color;
+use square::Square;
+
+/// The set of castling moves that are still available, one bit per king and side of the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CastlingRights(pub u8);
+
+impl BitAnd for CastlingRights {
+    type Output = CastlingRights;
+
+    fn bitand(self, rhs: CastlingRights) -> CastlingRights {
+        CastlingRights(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for CastlingRights {
+    type Output = CastlingRights;
+
+    fn bitor(self, rhs: CastlingRights) -> CastlingRights {
+        CastlingRights(self.0 | rhs.0)
+    }
+}
+
+impl Not for CastlingRights {
+    type Output = CastlingRights;
+
+    fn not(self) -> CastlingRights {
+        CastlingRights(!self.0 & CastlingRights::ALL.0)
+    }
+}
+
+// which rights are lost when a piece moves from or to each square. Only the kings' and rooks'
+// home squares matter: moving away from one gives up the right, and so does capturing on one.
+const UPDATE_MASK: [CastlingRights; 64] = {
+    let mut mask = [CastlingRights::NONE; 64];
+
+    mask[0] = CastlingRights::WHITE_OOO;
+    mask[4] = CastlingRights(CastlingRights::WHITE_OO.0 | CastlingRights::WHITE_OOO.0);
+    mask[7] = CastlingRights::WHITE_OO;
+    mask[56] = CastlingRights::BLACK_OOO;
+    mask[60] = CastlingRights(CastlingRights::BLACK_OO.0 | CastlingRights::BLACK_OOO.0);
+    mask[63] = CastlingRights::BLACK_OO;
+
+    mask
+};
+
+impl CastlingRights {
+    pub const NONE: CastlingRights = CastlingRights(0);
+    pub const WHITE_OO: CastlingRights = CastlingRights(1);
+    pub const WHITE_OOO: CastlingRights = CastlingRights(2);
+    pub const BLACK_OO: CastlingRights = CastlingRights(4);
+    pub const BLACK_OOO: CastlingRights = CastlingRights(8);
+    pub const ALL: CastlingRights = CastlingRights(15);
+
+    /// The right for `color` to castle on the `castling` side.
+    pub fn of(color: Color, castling: CastlingType) -> CastlingRights {
+        match (color, castling) {
+            (Color::White, CastlingType::Kingside) => CastlingRights::WHITE_OO,
+            (Color::White, CastlingType::Queenside) => CastlingRights::WHITE_OOO,
+            (Color::Black, CastlingType::Kingside) => CastlingRights::BLACK_OO,
+            (Color::Black, CastlingType::Queenside) => CastlingRights::BLACK_OOO
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: CastlingRights) -> bool {
+        self & other == other
+    }
+
+    pub fn can_castle(self, color: Color, castling: CastlingType) -> bool {
+        self.contains(CastlingRights::of(color, castling))
+    }
+
+    pub fn insert(&mut self, other: CastlingRights) {
+        *self = *self | other;
+    }
+
+    pub fn remove(&mut self, other: CastlingRights) {
+        *self = *self & !other;
+    }
+
+    /// The rights left after a move from `from` to `to`. This covers the king or a rook moving
+    /// off its home square as well as a rook being captured on it.
+    pub fn after_move(self, from: Square, to: Square) -> CastlingRights {
+        let lost = UPDATE_MASK[from.to_index() as usize] | UPDATE_MASK[to.to_index() as usize];
+        self & !lost
+    }
+}
+
+impl fmt::Display for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "-");
+        }
+
+        let flags = [
+            (CastlingRights::WHITE_OO, 'K'),
+            (CastlingRights::WHITE_OOO, 'Q'),
+            (CastlingRights::BLACK_OO, 'k'),
+            (CastlingRights::BLACK_OOO, 'q')
+        ];
+
+        for &(right, flag) in flags.iter() {
+            if self.contains(right) {
+                write!(f, "{}", flag)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_after_move() {
+    let moved = |from: &str, to: &str| {
+        CastlingRights::ALL.after_move(Square::from_san(from), Square::from_san(to)).to_string()
+    };
+
+    assert_eq!("KQkq", moved("g1", "f3"));
+    assert_eq!("kq", moved("e1", "e2"));
+    assert_eq!("Qkq", moved("h1", "h5"));
+    assert_eq!("KQk", moved("d5", "a8"));
+    // a rook trade on h8 costs both sides their kingside castling
+    assert_eq!("Qq", moved("h1", "h8"));
+    assert_eq!("KQ", moved("e8", "g8"));
+}
@@ -0,0 +1,195 @@
+//! Time management: deciding how long to think about a move, given what's left on the clock.
+
+use std::time::Duration;
+
+use motion::Move;
+use search::{Score, SearchInfo, MATE};
+
+/// How many more moves to budget for when the time control doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+/// The clock situation for the side to play, as a `go` command describes it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TimeControl {
+    /// What's left on our clock.
+    pub remaining: Option<Duration>,
+    /// What gets added to our clock after every move.
+    pub increment: Duration,
+    /// How many moves until the next time control, or `None` if the rest of the game has to be
+    /// played on what's left.
+    pub moves_to_go: Option<u32>,
+    /// Exactly how long to think, whatever the clock says.
+    pub move_time: Option<Duration>,
+    /// Time that passes without the engine thinking, like the GUI reading the move and
+    /// pressing the clock. It's kept in reserve so that the engine doesn't lose on time.
+    pub overhead: Duration
+}
+
+/// How long a search may take. The soft limit is what a search aims for, and can stretch when
+/// the search is unsure of itself. The hard limit is where it gets cut off no matter what.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration
+}
+
+impl TimeControl {
+    /// The budget for this move, or `None` if there's no limit on time at all.
+    pub fn budget(&self) -> Option<TimeBudget> {
+        if let Some(move_time) = self.move_time {
+            let time = move_time.saturating_sub(self.overhead);
+            return Some(TimeBudget { soft: time, hard: time });
+        }
+
+        let available = self.remaining?.saturating_sub(self.overhead);
+        let moves_to_go = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+
+        // most of the increment can be spent, since it comes back once the move is made, but
+        // never more than half the clock on one move
+        let soft = (available / moves_to_go + self.increment * 3 / 4).min(available / 2);
+        let hard = (soft * 4).min(available * 3 / 4);
+
+        Some(TimeBudget { soft, hard })
+    }
+}
+
+/// Decides, after each iteration of a search, whether it's worth starting another one.
+pub struct TimeManager {
+    budget: TimeBudget,
+    /// Whether the side to play has only one legal move, in which case there's nothing to think
+    /// about.
+    only_move: bool,
+    best_move: Option<Move>,
+    score: Option<i32>,
+    /// How many iterations in a row have agreed on the best move.
+    stable_iterations: u32
+}
+
+impl TimeManager {
+    pub fn new(budget: TimeBudget, only_move: bool) -> TimeManager {
+        TimeManager {
+            budget,
+            only_move,
+            best_move: None,
+            score: None,
+            stable_iterations: 0
+        }
+    }
+
+    /// Takes in what an iteration found, and returns whether to search another one. `elapsed` is
+    /// how long the search has taken so far.
+    pub fn should_continue(&mut self, info: &SearchInfo, elapsed: Duration) -> bool {
+        if self.only_move {
+            return false;
+        }
+
+        let best_move = info.best_move();
+        if best_move == self.best_move {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+        }
+
+        // spend longer when the best move keeps changing, and less once it has settled down
+        let mut scale = match self.stable_iterations {
+            0 => 1.5,
+            1 => 1.2,
+            2 | 3 => 1.0,
+            _ => 0.8
+        };
+
+        // a falling score means trouble, which is worth spending time to get out of
+        let score = score_value(info.score);
+        if let Some(previous) = self.score {
+            let drop = (previous - score).clamp(0, 200);
+            scale *= 1.0 + drop as f64 / 200.0;
+        }
+
+        self.best_move = best_move;
+        self.score = Some(score);
+
+        // a new iteration takes longer than all the ones before it put together, so there's no
+        // point starting one that can't finish before the soft limit
+        let soft = self.budget.soft.mul_f64(scale).min(self.budget.hard);
+        elapsed < soft / 2
+    }
+}
+
+fn score_value(score: Score) -> i32 {
+    match score {
+        Score::Centipawns(centipawns) => centipawns,
+        Score::Mate(moves) if moves > 0 => MATE - moves,
+        Score::Mate(moves) => -MATE - moves
+    }
+}
+
+#[test]
+fn budgets() {
+    let ms = Duration::from_millis;
+
+    let sudden_death = TimeControl {
+        remaining: Some(ms(60_000)),
+        overhead: ms(100),
+        ..TimeControl::default()
+    };
+    let budget = sudden_death.budget().unwrap();
+    assert_eq!(ms(59_900) / 30, budget.soft);
+    assert_eq!(budget.soft * 4, budget.hard);
+
+    // increments get spent, but the last move before a time control can't use the whole clock
+    let increment = TimeControl { increment: ms(2_000), ..sudden_death };
+    assert_eq!(ms(59_900) / 30 + ms(1_500), increment.budget().unwrap().soft);
+
+    let last_move = TimeControl { moves_to_go: Some(1), ..increment };
+    assert_eq!(TimeBudget { soft: ms(29_950), hard: ms(44_925) }, last_move.budget().unwrap());
+
+    // even the hard limit leaves time on the clock
+    for &remaining in [ms(0), ms(50), ms(500), ms(3_000)].iter() {
+        let control = TimeControl { remaining: Some(remaining), ..increment };
+        let budget = control.budget().unwrap();
+        assert!(budget.soft <= budget.hard);
+        assert!(budget.hard + control.overhead <= remaining.max(control.overhead));
+    }
+
+    let move_time = TimeControl { move_time: Some(ms(1_000)), ..sudden_death };
+    assert_eq!(TimeBudget { soft: ms(900), hard: ms(900) }, move_time.budget().unwrap());
+
+    assert_eq!(None, TimeControl::default().budget());
+}
+
+#[test]
+fn iterations() {
+    use position::Position;
+
+    let position = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    let info = |uci: &str, score: i32| SearchInfo {
+        depth: 1,
+        score: Score::Centipawns(score),
+        nodes: 0,
+        time: Duration::default(),
+        hashfull: 0,
+        pv: vec![position.parse_uci(uci).unwrap()]
+    };
+
+    let budget = TimeBudget { soft: Duration::from_millis(1_000), hard: Duration::from_secs(4) };
+    let at = Duration::from_millis;
+
+    // once the best move has settled down, the search stops sooner
+    let mut time = TimeManager::new(budget, false);
+    assert!(time.should_continue(&info("e2e4", 50), at(700)));
+    assert!(time.should_continue(&info("e2e4", 50), at(550)));
+    assert!(!time.should_continue(&info("e2e4", 50), at(550)));
+    for _ in 0..2 {
+        time.should_continue(&info("e2e4", 50), at(0));
+    }
+    assert!(!time.should_continue(&info("e2e4", 50), at(450)));
+
+    // a new best move, or a falling score, buys more time
+    assert!(time.should_continue(&info("e1d2", 50), at(700)));
+    assert!(!time.should_continue(&info("e1d2", 50), at(700)));
+    assert!(time.should_continue(&info("e1d2", -150), at(700)));
+
+    // nothing to think about
+    let mut time = TimeManager::new(budget, true);
+    assert!(!time.should_continue(&info("e2e4", 50), at(0)));
+}
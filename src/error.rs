@@ -0,0 +1,151 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use fen;
+use motion::Move;
+use square::Square;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// Text that isn't a square in algebraic notation, like "e4".
+    InvalidSquare(String),
+    InvalidFen {
+        field: FenField,
+        reason: String
+    },
+    /// A FEN that parses fine, but describes a position that can't come up in a game.
+    InvalidPosition(String),
+    NoPieceOnSquare(Square),
+    IllegalMove(Move),
+    /// A move in Standard Algebraic Notation that doesn't pick out exactly one legal move.
+    InvalidSan {
+        san: String,
+        reason: String
+    },
+    /// A move in UCI's long algebraic notation that isn't a legal move.
+    InvalidUci {
+        uci: String,
+        reason: String
+    },
+    /// A problem with PGN input, and the line it was found on.
+    Pgn {
+        line: usize,
+        reason: String
+    },
+    /// Reading or writing failed. Only the message is kept, so that `Error` stays comparable.
+    Io(String)
+}
+
+/// The six space-separated fields of a FEN record, in order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FenField {
+    Placement,
+    SideToPlay,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber
+}
+
+impl FenField {
+    const ALL: [FenField; 6] = [
+        FenField::Placement,
+        FenField::SideToPlay,
+        FenField::Castling,
+        FenField::EnPassant,
+        FenField::HalfmoveClock,
+        FenField::FullmoveNumber
+    ];
+}
+
+impl Error {
+    /// Converts an error from the `fen` crate, which needs the FEN it came from to work out
+    /// which field was missing when there weren't enough of them.
+    pub fn from_fen_error(fen: &str, error: fen::FenError) -> Error {
+        let (field, reason) = match error {
+            // the `fen` crate also gives this error when there are too many fields
+            fen::FenError::NotEnoughParts => match FenField::ALL.get(fen.split(' ').count()) {
+                Some(&field) => (field, "missing".to_string()),
+                None => (FenField::FullmoveNumber, "followed by extra fields".to_string())
+            },
+            fen::FenError::BadPlacement(text) => {
+                (FenField::Placement, format!("bad rank {:?}", text))
+            },
+            fen::FenError::TooManyPieces(text) => {
+                (FenField::Placement, format!("too many squares in rank {:?}", text))
+            },
+            fen::FenError::UnknownPiece(piece) => {
+                (FenField::Placement, format!("unknown piece {:?}", piece))
+            },
+            fen::FenError::NoSuchSide(text) => {
+                (FenField::SideToPlay, format!("unknown side {:?}", text))
+            },
+            fen::FenError::BadEnPassant(text) => {
+                (FenField::EnPassant, format!("bad square {:?}", text))
+            },
+            fen::FenError::BadHalfmove(text) => {
+                (FenField::HalfmoveClock, format!("bad number {:?}", text))
+            },
+            fen::FenError::BadFullmove(text) => {
+                (FenField::FullmoveNumber, format!("bad number {:?}", text))
+            }
+        };
+
+        Error::InvalidFen { field, reason }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidSquare(ref text) => write!(f, "invalid square: {:?}", text),
+            Error::InvalidFen { field, ref reason } => {
+                write!(f, "invalid FEN: {:?} field: {}", field, reason)
+            },
+            Error::InvalidPosition(ref reason) => write!(f, "invalid position: {}", reason),
+            Error::NoPieceOnSquare(square) => write!(f, "no piece on {}", square),
+            Error::IllegalMove(motion) => write!(f, "illegal move {}", motion),
+            Error::InvalidSan { ref san, ref reason } => {
+                write!(f, "invalid move {:?}: {}", san, reason)
+            },
+            Error::InvalidUci { ref uci, ref reason } => {
+                write!(f, "invalid move {:?}: {}", uci, reason)
+            },
+            Error::Pgn { line, ref reason } => {
+                write!(f, "invalid PGN on line {}: {}", line, reason)
+            },
+            Error::Io(ref message) => write!(f, "I/O error: {}", message)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error.to_string())
+    }
+}
+
+#[test]
+fn fen_errors() {
+    let missing = Error::InvalidFen {
+        field: FenField::EnPassant,
+        reason: "missing".to_string()
+    };
+
+    let fen = "8/8/8/8/8/8/8/8 w KQkq";
+    assert_eq!(missing, Error::from_fen_error(fen, fen::BoardState::from_fen(fen).unwrap_err()));
+
+    let fen = "8/8/8/8/8/8/8/8 w - - 0 1 2";
+    let error = Error::from_fen_error(fen, fen::BoardState::from_fen(fen).unwrap_err());
+    assert_eq!("invalid FEN: FullmoveNumber field: followed by extra fields", error.to_string());
+
+    let fen = "8/8/8/8/8/8/8/8 x - - 0 1";
+    let error = Error::from_fen_error(fen, fen::BoardState::from_fen(fen).unwrap_err());
+    assert_eq!("invalid FEN: SideToPlay field: unknown side \"x\"", error.to_string());
+}
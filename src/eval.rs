@@ -0,0 +1,391 @@
+//! Static evaluation: a quick guess at how good a position is, without searching any moves.
+//!
+//! Every term has a middlegame and an endgame value, which are blended according to how much
+//! material is left on the board.
+
+use std::fmt;
+use std::ops::{AddAssign, Sub};
+
+use bitboard::Bitboard;
+use magic::MagicDatabase;
+use movegen::bitmask;
+use position::{Color, Position, PieceKind};
+
+/// The game phase of a position with all of its pieces. Knights and bishops count 1, rooks 2 and
+/// queens 4, so that the phase falls towards 0 as pieces come off.
+pub const MAX_PHASE: i32 = 24;
+
+/// A score split into the terms that make it up, so that it's possible to see why a position got
+/// it. Every term is in centipawns from the side to play's point of view, and has already been
+/// blended between its middlegame and endgame values.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Breakdown {
+    /// How much material is left, from 0 (only kings and pawns) to `MAX_PHASE`.
+    pub phase: i32,
+    pub material: i32,
+    pub piece_squares: i32,
+    pub mobility: i32,
+    pub pawn_structure: i32
+}
+
+impl Breakdown {
+    pub fn total(&self) -> i32 {
+        self.material + self.piece_squares + self.mobility + self.pawn_structure
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Phase:          {:>6} / {}", self.phase, MAX_PHASE)?;
+        writeln!(f, "Material:       {:>6}", self.material)?;
+        writeln!(f, "Piece squares:  {:>6}", self.piece_squares)?;
+        writeln!(f, "Mobility:       {:>6}", self.mobility)?;
+        writeln!(f, "Pawn structure: {:>6}", self.pawn_structure)?;
+        write!(f, "Total:          {:>6}", self.total())
+    }
+}
+
+/// Scores `position` in centipawns from the side to play's point of view.
+pub fn evaluate(position: &Position) -> i32 {
+    breakdown(position).total()
+}
+
+/// Scores `position` like `evaluate`, but keeps each term separate.
+pub fn breakdown(position: &Position) -> Breakdown {
+    let phase = phase(position);
+    let terms = side_terms(position, Color::White) - side_terms(position, Color::Black);
+
+    let sign = match position.side_to_play {
+        Color::White => 1,
+        Color::Black => -1
+    };
+
+    Breakdown {
+        phase,
+        material: sign * terms.material.blend(phase),
+        piece_squares: sign * terms.piece_squares.blend(phase),
+        mobility: sign * terms.mobility.blend(phase),
+        pawn_structure: sign * terms.pawn_structure.blend(phase)
+    }
+}
+
+pub fn phase(position: &Position) -> i32 {
+    let count = |bitboard: Bitboard| bitboard.num_occupied_squares() as i32;
+
+    let phase = count(position.white.knights | position.black.knights)
+        + count(position.white.bishops | position.black.bishops)
+        + 2 * count(position.white.rooks | position.black.rooks)
+        + 4 * count(position.white.queens | position.black.queens);
+
+    // promotions can take the phase past where it started
+    phase.min(MAX_PHASE)
+}
+
+/// A middlegame value and an endgame value.
+#[derive(Clone, Copy, Debug, Default)]
+struct Tapered {
+    mg: i32,
+    eg: i32
+}
+
+impl Tapered {
+    fn blend(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, rhs: Tapered) {
+        self.mg += rhs.mg;
+        self.eg += rhs.eg;
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Tapered;
+
+    fn sub(self, rhs: Tapered) -> Tapered {
+        Tapered { mg: self.mg - rhs.mg, eg: self.eg - rhs.eg }
+    }
+}
+
+/// Every term for one side, before blending.
+#[derive(Clone, Copy, Debug, Default)]
+struct Terms {
+    material: Tapered,
+    piece_squares: Tapered,
+    mobility: Tapered,
+    pawn_structure: Tapered
+}
+
+impl Sub for Terms {
+    type Output = Terms;
+
+    fn sub(self, rhs: Terms) -> Terms {
+        Terms {
+            material: self.material - rhs.material,
+            piece_squares: self.piece_squares - rhs.piece_squares,
+            mobility: self.mobility - rhs.mobility,
+            pawn_structure: self.pawn_structure - rhs.pawn_structure
+        }
+    }
+}
+
+const PIECE_KINDS: [PieceKind; 6] = [PieceKind::Pawn, PieceKind::Knight, PieceKind::Bishop,
+                                     PieceKind::Rook, PieceKind::Queen, PieceKind::King];
+
+fn side_terms(position: &Position, color: Color) -> Terms {
+    let magic = MagicDatabase::shared();
+    let army = position.get_army(color);
+    let mut terms = Terms::default();
+
+    for &kind in PIECE_KINDS.iter() {
+        let (mg_table, eg_table) = piece_square_tables(kind);
+
+        for square in army.get_bitboard(kind).squares() {
+            terms.material += material(kind);
+
+            // the tables are laid out the way White sees the board, with the eighth rank first
+            let index = match color {
+                Color::White => square.to_index() ^ 56,
+                Color::Black => square.to_index()
+            } as usize;
+            terms.piece_squares += Tapered { mg: mg_table[index], eg: eg_table[index] };
+
+            let attacks = match kind {
+                PieceKind::Knight => bitmask::knight_moves(square),
+                PieceKind::Bishop => magic.bishop_attacks(square, position.all),
+                PieceKind::Rook => magic.rook_attacks(square, position.all),
+                PieceKind::Queen => magic.queen_attacks(square, position.all),
+                PieceKind::Pawn | PieceKind::King => continue
+            };
+
+            let moves = (attacks & !army.all).num_occupied_squares() as i32;
+            let weight = mobility_weight(kind);
+            terms.mobility += Tapered { mg: weight.mg * moves, eg: weight.eg * moves };
+        }
+    }
+
+    terms.pawn_structure = pawn_structure(army.pawns, position.get_army(color.other()).pawns,
+                                          color);
+    terms
+}
+
+/// Roughly what a piece is worth, for when there's no time to work out the whole score.
+pub fn piece_value(kind: PieceKind) -> i32 {
+    material(kind).mg
+}
+
+fn material(kind: PieceKind) -> Tapered {
+    match kind {
+        PieceKind::Pawn => Tapered { mg: 82, eg: 94 },
+        PieceKind::Knight => Tapered { mg: 337, eg: 281 },
+        PieceKind::Bishop => Tapered { mg: 365, eg: 297 },
+        PieceKind::Rook => Tapered { mg: 477, eg: 512 },
+        PieceKind::Queen => Tapered { mg: 1025, eg: 936 },
+        PieceKind::King => Tapered::default()
+    }
+}
+
+/// What each square a piece can move to is worth.
+fn mobility_weight(kind: PieceKind) -> Tapered {
+    match kind {
+        PieceKind::Knight => Tapered { mg: 4, eg: 4 },
+        PieceKind::Bishop => Tapered { mg: 5, eg: 5 },
+        PieceKind::Rook => Tapered { mg: 2, eg: 4 },
+        PieceKind::Queen => Tapered { mg: 1, eg: 2 },
+        PieceKind::Pawn | PieceKind::King => Tapered::default()
+    }
+}
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+const DOUBLED_PAWN: Tapered = Tapered { mg: -10, eg: -20 };
+const ISOLATED_PAWN: Tapered = Tapered { mg: -10, eg: -15 };
+
+/// The bonus for a passed pawn, by how many ranks it has moved up the board.
+const PASSED_PAWN: [Tapered; 8] = [
+    Tapered { mg: 0, eg: 0 },
+    Tapered { mg: 5, eg: 10 },
+    Tapered { mg: 5, eg: 15 },
+    Tapered { mg: 10, eg: 25 },
+    Tapered { mg: 20, eg: 45 },
+    Tapered { mg: 35, eg: 75 },
+    Tapered { mg: 55, eg: 120 },
+    Tapered { mg: 0, eg: 0 }
+];
+
+/// Doubled and isolated pawns are weaknesses, and passed pawns are strengths.
+fn pawn_structure(pawns: Bitboard, enemy_pawns: Bitboard, color: Color) -> Tapered {
+    let mut score = Tapered::default();
+
+    for file in 0..8 {
+        let on_file = (pawns.0 & (FILE_A << file)).count_ones() as i32;
+        if on_file > 1 {
+            score += Tapered { mg: DOUBLED_PAWN.mg * (on_file - 1),
+                               eg: DOUBLED_PAWN.eg * (on_file - 1) };
+        }
+    }
+
+    for square in pawns.squares() {
+        let file = square.file();
+        let rank = square.rank();
+
+        let neighbors = adjacent_files(file);
+        if pawns.0 & neighbors == 0 {
+            score += ISOLATED_PAWN;
+        }
+
+        let ahead = match color {
+            Color::White => !0 << (8 * (rank + 1)),
+            Color::Black => (1 << (8 * rank)) - 1
+        };
+        if enemy_pawns.0 & (neighbors | FILE_A << file) & ahead == 0 {
+            let advanced = match color {
+                Color::White => rank,
+                Color::Black => 7 - rank
+            };
+            score += PASSED_PAWN[advanced as usize];
+        }
+    }
+
+    score
+}
+
+fn adjacent_files(file: u8) -> u64 {
+    let left = if file > 0 { FILE_A << (file - 1) } else { 0 };
+    let right = if file < 7 { FILE_A << (file + 1) } else { 0 };
+    left | right
+}
+
+fn piece_square_tables(kind: PieceKind) -> (&'static [i32; 64], &'static [i32; 64]) {
+    match kind {
+        PieceKind::Pawn => (&PAWN_MG, &PAWN_EG),
+        PieceKind::Knight => (&KNIGHT, &KNIGHT),
+        PieceKind::Bishop => (&BISHOP, &BISHOP),
+        PieceKind::Rook => (&ROOK, &ROOK),
+        PieceKind::Queen => (&QUEEN, &QUEEN),
+        PieceKind::King => (&KING_MG, &KING_EG)
+    }
+}
+
+const PAWN_MG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+const PAWN_EG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      5,   5,   5,   5,   5,   5,   5,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+const KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50
+];
+
+const BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20
+];
+
+const ROOK: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0
+];
+
+const QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20
+];
+
+const KING_MG: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20
+];
+
+const KING_EG: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50
+];
+
+#[test]
+fn symmetric_positions() {
+    let start = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        .unwrap();
+    assert_eq!(0, evaluate(&start));
+    assert_eq!(MAX_PHASE, breakdown(&start).phase);
+
+    // the same position with the colors swapped and the board flipped
+    let white = Position::from_fen("r1bqk2r/pp3ppp/2n2n2/2bp4/8/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - \
+                                    0 1").unwrap();
+    let black = Position::from_fen("r1bqkb1r/pppp1ppp/2n2n2/8/2BP4/2N2N2/PP3PPP/R1BQK2R b KQkq - \
+                                    0 1").unwrap();
+    assert_eq!(breakdown(&white), breakdown(&black));
+    assert!(breakdown(&white).material > 0);
+}
+
+#[test]
+fn evaluation_terms() {
+    // a passed pawn is worth more the further it gets, especially with no pieces around
+    let position = Position::from_fen("4k3/8/8/8/8/P7/8/4K3 w - - 0 1").unwrap();
+    let advanced = Position::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(0, breakdown(&position).phase);
+    assert!(breakdown(&advanced).pawn_structure > breakdown(&position).pawn_structure);
+
+    // White's pawns are doubled and isolated, and Black's is only isolated
+    let position = Position::from_fen("4k3/3p4/8/8/8/3P4/3P4/4K3 b - - 0 1").unwrap();
+    let breakdown = breakdown(&position);
+    assert!(breakdown.pawn_structure > 0);
+    assert_eq!(breakdown.material + breakdown.piece_squares + breakdown.mobility
+                   + breakdown.pawn_structure,
+               evaluate(&position));
+}
@@ -1,9 +1,22 @@
 extern crate fen;
 extern crate rand;
 
+pub mod error;
+pub mod eval;
 pub mod square;
 pub mod bitboard;
 pub mod motion;
 pub mod position;
+pub mod castling;
+pub mod clock;
 pub mod magic;
+pub mod notation;
+pub mod ordering;
+pub mod outcome;
+pub mod perft;
+pub mod pgn;
+pub mod search;
+pub mod see;
+pub mod transposition;
+pub mod zobrist;
 mod movegen;
@@ -1,4 +1,5 @@
 use std::num::Wrapping;
+use std::sync::OnceLock;
 
 use bitboard::Bitboard;
 use square::Square;
@@ -11,6 +12,12 @@ pub struct MagicDatabase {
     bishop_attacks: Vec<Bitboard>
 }
 
+impl Default for MagicDatabase {
+    fn default() -> MagicDatabase {
+        MagicDatabase::new()
+    }
+}
+
 impl MagicDatabase {
     pub fn new() -> MagicDatabase {
         let mut db = MagicDatabase {
@@ -20,16 +27,14 @@ impl MagicDatabase {
             bishop_attacks: Vec::new()
         };
 
-        for square_index in 0..64 {
-            let (magic, shift_amount) = ROOK_MAGICS[square_index];
+        for (square_index, &(magic, shift_amount)) in ROOK_MAGICS.iter().enumerate() {
             let database = gen_rook_database(square_index as u8, magic, shift_amount).unwrap();
             db.rook_databases.push(database);
 
             db.rook_attacks.push(rook_attacks(Square::new(square_index as u8)));
         }
 
-        for square_index in 0..64 {
-            let (magic, shift_amount) = BISHOP_MAGICS[square_index];
+        for (square_index, &(magic, shift_amount)) in BISHOP_MAGICS.iter().enumerate() {
             let database = gen_bishop_database(square_index as u8, magic, shift_amount).unwrap();
             db.bishop_databases.push(database);
 
@@ -39,6 +44,13 @@ impl MagicDatabase {
         db
     }
 
+    /// Returns a process-wide database, building it the first time it's asked for. Building one
+    /// takes a while, so prefer this over `MagicDatabase::new` outside of tests.
+    pub fn shared() -> &'static MagicDatabase {
+        static SHARED: OnceLock<MagicDatabase> = OnceLock::new();
+        SHARED.get_or_init(MagicDatabase::new)
+    }
+
     pub fn rook_attacks(&self, square: Square, occupied: Bitboard) -> Bitboard {
         let square_index = square.to_index() as usize;
 
@@ -77,8 +89,8 @@ fn gen_bishop_database(square_index: u8, magic: u64, shift_amount: u32) -> Optio
     gen_database(&bishop_attacks, &bishop_move_locations, square_index, magic, shift_amount)
 }
 
-fn gen_database(attacks: &Fn(Square) -> Bitboard,
-                solver: &Fn(Square, Bitboard) -> Bitboard,
+fn gen_database(attacks: &dyn Fn(Square) -> Bitboard,
+                solver: &dyn Fn(Square, Bitboard) -> Bitboard,
                 square_index: u8, magic: u64, shift_amount: u32) -> Option<Vec<Bitboard>> {
     let square = Square::new(square_index);
     let attacks = attacks(square);
@@ -98,8 +110,8 @@ pub fn find_bishop_magic(square_index: u8) -> (u64, u32) {
     find_magic(&bishop_attacks, &bishop_move_locations, square_index)
 }
 
-fn find_magic(attacks: &Fn(Square) -> Bitboard,
-              solver: &Fn(Square, Bitboard) -> Bitboard,
+fn find_magic(attacks: &dyn Fn(Square) -> Bitboard,
+              solver: &dyn Fn(Square, Bitboard) -> Bitboard,
               square_index: u8) -> (u64, u32) {
     let square = Square::new(square_index);
     let attacks = attacks(square);
@@ -128,7 +140,7 @@ fn gen_variations(bitboard: Bitboard) -> Vec<Bitboard> {
     let rest_variations = gen_variations(without_top);
 
     let with_one: Vec<_> = rest_variations.iter()
-        .map(|bitboard| bitboard.clone() | top_one_square.to_bitboard())
+        .map(|bitboard| *bitboard | top_one_square.to_bitboard())
         .collect();
     let without_one = rest_variations;
 
@@ -141,13 +153,13 @@ fn gen_variations(bitboard: Bitboard) -> Vec<Bitboard> {
 
 fn gen_magic_database(magic: u64, num_bits: u32, square: Square,
              variations: &[Bitboard],
-             solver: &Fn(Square, Bitboard) -> Bitboard) -> Option<Vec<Bitboard>> {
+             solver: &dyn Fn(Square, Bitboard) -> Bitboard) -> Option<Vec<Bitboard>> {
     let database_size = 2usize.pow(num_bits);
     let mut database = vec![Bitboard::new(0); database_size];
 
     for variation in variations {
-        let index = magic_index(magic, num_bits, variation.clone());
-        let solution = solver(square, variation.clone());
+        let index = magic_index(magic, num_bits, *variation);
+        let solution = solver(square, *variation);
 
         if database[index].is_empty() {
             database[index] = solution;
@@ -234,7 +246,7 @@ pub fn bishop_attacks(square: Square) -> Bitboard {
 
 fn diagonal_attacks(start: (i8, i8), dx: i8, dy: i8) -> Bitboard {
     fn is_in_bounds(file: i8, rank: i8) -> bool {
-        1 <= file && file <= 6 && 1 <= rank && rank <= 6
+        (1..=6).contains(&file) && (1..=6).contains(&rank)
     }
 
     let mut result = Bitboard::new(0);
@@ -263,7 +275,7 @@ pub fn bishop_move_locations(square: Square, enemies: Bitboard) -> Bitboard {
 
 fn diagonal_move_locations(start: (i8, i8), dx: i8, dy: i8, enemies: Bitboard) -> Bitboard {
     fn is_in_bounds(file: i8, rank: i8) -> bool {
-        0 <= file && file < 8 && 0 <= rank && rank < 8
+        (0..8).contains(&file) && (0..8).contains(&rank)
     }
 
     fn occupied_by_enemy(file: i8, rank: i8, enemies: Bitboard) -> bool {
@@ -1,3 +1,6 @@
+use std::fmt;
+
+use notation;
 use square::Square;
 use position::PieceKind;
 
@@ -14,3 +17,90 @@ pub enum CastlingType {
     Kingside,
     Queenside
 }
+
+impl Move {
+    /// Writes the move in the long algebraic notation UCI uses, like "e2e4" or "e7e8q". Castling
+    /// is written as the king's move.
+    pub fn to_uci(&self) -> String {
+        self.to_string()
+    }
+
+    /// Packs the move into 16 bits: 6 for each square, 2 for what kind of move it is, and 2 for
+    /// what it promotes to. Nothing packs to 0, since no move goes from a1 to a1.
+    pub fn pack(&self) -> u16 {
+        let (special, promotion) = match (self.promote_to, self.castling) {
+            (Some(PieceKind::Knight), _) => (1, 0),
+            (Some(PieceKind::Bishop), _) => (1, 1),
+            (Some(PieceKind::Rook), _) => (1, 2),
+            (Some(_), _) => (1, 3),
+            (None, Some(_)) => (2, 0),
+            (None, None) => (0, 0)
+        };
+
+        self.from.to_index() as u16 | (self.to.to_index() as u16) << 6 | special << 12
+            | promotion << 14
+    }
+
+    /// Undoes `pack`. Castling is told apart by which way the king goes.
+    pub fn unpack(packed: u16) -> Option<Move> {
+        if packed == 0 {
+            return None;
+        }
+
+        let from = Square::new((packed & 63) as u8);
+        let to = Square::new((packed >> 6 & 63) as u8);
+        let promotion = [PieceKind::Knight, PieceKind::Bishop, PieceKind::Rook, PieceKind::Queen];
+
+        let (promote_to, castling) = match packed >> 12 & 3 {
+            1 => (Some(promotion[(packed >> 14) as usize]), None),
+            2 if to.file() > from.file() => (None, Some(CastlingType::Kingside)),
+            2 => (None, Some(CastlingType::Queenside)),
+            _ => (None, None)
+        };
+
+        Some(Move { from, to, promote_to, castling })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+
+        if let Some(promote_to) = self.promote_to {
+            write!(f, "{}", notation::piece_letter(promote_to).to_ascii_lowercase())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn uci_notation() {
+    let motion = Move {
+        from: Square::from_san("e7"),
+        to: Square::from_san("e8"),
+        promote_to: Some(PieceKind::Knight),
+        castling: None
+    };
+    assert_eq!("e7e8n", motion.to_uci());
+
+    let motion = Move {
+        from: Square::from_san("e1"),
+        to: Square::from_san("g1"),
+        promote_to: None,
+        castling: Some(CastlingType::Kingside)
+    };
+    assert_eq!("e1g1", motion.to_string());
+}
+
+#[test]
+fn packing() {
+    use position::Position;
+
+    let position = Position::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    for motion in position.legal_moves() {
+        assert_eq!(Some(motion), Move::unpack(motion.pack()));
+    }
+
+    assert_eq!(None, Move::unpack(0));
+}
@@ -1,13 +1,54 @@
+pub use self::iter::MovesIter;
+
+use bitboard::Bitboard;
+use magic::MagicDatabase;
+use position::Position;
+use square::Square;
+
+/// All pieces, of either color, that attack `square` if the board were occupied by `occupied`.
+pub fn attackers_to(position: &Position, magic: &MagicDatabase, square: Square,
+                    occupied: Bitboard) -> Bitboard {
+    let (white, black) = (&position.white, &position.black);
+
+    let bishops = white.bishops | white.queens | black.bishops | black.queens;
+    let rooks = white.rooks | white.queens | black.rooks | black.queens;
+
+    (bitmask::black_pawn_attacks(square) & white.pawns)
+        | (bitmask::white_pawn_attacks(square) & black.pawns)
+        | (bitmask::knight_moves(square) & (white.knights | black.knights))
+        | (bitmask::king_moves(square) & (white.king | black.king))
+        | (magic.bishop_attacks(square, occupied) & bishops)
+        | (magic.rook_attacks(square, occupied) & rooks)
+}
+
 mod iter {
     use bitboard::Bitboard;
-    use motion::Move;
+    use magic::MagicDatabase;
+    use motion::{CastlingType, Move};
     use square::Square;
-    use position::{Color, Piece, PieceKind, Position};
+    use position::{en_passant_victim, Color, Piece, PieceKind, Position};
 
-    struct MovesIter<'a> {
+    pub struct MovesIter<'a> {
         position: &'a Position,
+        magic: &'a MagicDatabase,
         next_to_return: Option<Piece>,
         buffer: Vec<Move>,
+        legality: Option<Legality>,
+        /// The only squares moves may go to. Promotions are the exception: they change the
+        /// material on the board like captures do, so they're generated wherever they go.
+        targets: Bitboard
+    }
+
+    /// What the side to play has to respect for its moves to be legal, worked out once up front
+    /// from the position's bitboards.
+    struct Legality {
+        king: Square,
+        /// The squares a piece other than the king can move to: the checker and the squares
+        /// between it and the king when in check, none in double check, and all of them
+        /// otherwise.
+        check_mask: Bitboard,
+        /// Our pieces that are the only thing standing between our king and an enemy slider.
+        pinned: Bitboard
     }
 
     impl<'a> Iterator for MovesIter<'a> {
@@ -29,29 +70,105 @@ mod iter {
     }
 
     impl<'a> MovesIter<'a> {
-        fn new(position: &Position) -> MovesIter {
+        /// Generates pseudo-legal moves, which may leave the side to play in check.
+        pub fn new(position: &'a Position, magic: &'a MagicDatabase) -> MovesIter<'a> {
             let next_to_return = Piece::new(position.side_to_play, PieceKind::Pawn);
             MovesIter {
-                position: position,
+                position,
+                magic,
                 next_to_return: Some(next_to_return),
-                buffer: vec![]
+                buffer: vec![],
+                legality: None,
+                targets: Bitboard::new(!0)
+            }
+        }
+
+        /// Generates only legal moves. Positions without a king for the side to play are treated
+        /// as if that side can never be in check.
+        pub fn legal(position: &'a Position, magic: &'a MagicDatabase) -> MovesIter<'a> {
+            let mut iter = MovesIter::new(position, magic);
+            iter.legality = Legality::new(position, magic);
+            iter
+        }
+
+        /// Only generates moves to `targets`, along with any promotions. Passing the enemy's
+        /// pieces generates just the captures and promotions, without any quiet moves.
+        pub fn with_targets(mut self, targets: Bitboard) -> MovesIter<'a> {
+            self.targets = targets;
+            self
+        }
+
+        /// The squares the piece on `from` may move to without exposing or ignoring a check.
+        fn move_mask(&self, from: Square) -> Bitboard {
+            match self.legality {
+                Some(ref legality) => {
+                    if legality.pinned.is_occupied(from) {
+                        legality.check_mask & super::bitmask::line(self.magic, legality.king, from)
+                    } else {
+                        legality.check_mask
+                    }
+                },
+
+                None => Bitboard::new(!0)
             }
         }
 
+        /// Taking en passant removes two pieces from the board at once, which can expose our king
+        /// in ways the pins don't account for (e.g. both pawns leaving the king's rank), so play
+        /// the capture out on the bitboards instead.
+        fn en_passant_is_legal(&self, from: Square, to: Square, victim: Square) -> bool {
+            let legality = match self.legality {
+                Some(ref legality) => legality,
+                None => return true
+            };
+
+            let occupied = (self.position.all ^ from.to_bitboard() ^ victim.to_bitboard())
+                | to.to_bitboard();
+            let them = self.position.get_army(self.position.side_to_play.other()).all
+                ^ victim.to_bitboard();
+
+            let attackers = super::attackers_to(self.position, self.magic, legality.king, occupied);
+            (attackers & them).is_empty()
+        }
+
+        /// Removes the squares from `targets` where our king would be attacked.
+        fn king_safe_squares(&self, targets: Bitboard) -> Bitboard {
+            let legality = match self.legality {
+                Some(ref legality) => legality,
+                None => return targets
+            };
+
+            // the king can't hide from a slider by stepping back along the line it's attacked on,
+            // so look through it when computing attacks
+            let occupied = self.position.all ^ legality.king.to_bitboard();
+            let them = self.position.get_army(self.position.side_to_play.other()).all;
+
+            let mut safe = Bitboard::new(0);
+            for to in targets.squares() {
+                let attackers = super::attackers_to(self.position, self.magic, to, occupied);
+
+                if (attackers & them).is_empty() {
+                    safe = safe | to.to_bitboard();
+                }
+            }
+
+            safe
+        }
+
         fn get_moves(&mut self, piece: Piece) {
             match (piece.color, piece.kind) {
                 (Color::White, PieceKind::Pawn) => self.get_white_pawn_moves(),
                 (Color::White, PieceKind::Knight) => self.get_white_knight_moves(),
-                (Color::White, PieceKind::Bishop) => {},
-                (Color::White, PieceKind::Rook) => {},
-                (Color::White, PieceKind::Queen) => {},
+                (Color::White, PieceKind::Bishop) => self.get_white_bishop_moves(),
+                (Color::White, PieceKind::Rook) => self.get_white_rook_moves(),
+                (Color::White, PieceKind::Queen) => self.get_white_queen_moves(),
                 (Color::White, PieceKind::King) => self.get_white_king_moves(),
 
                 (Color::Black, PieceKind::Pawn) => self.get_black_pawn_moves(),
                 (Color::Black, PieceKind::Knight) => self.get_black_knight_moves(),
-                (Color::Black, PieceKind::Bishop) => {},
-                (Color::Black, PieceKind::Rook) => {},
-                (Color::Black, PieceKind::Queen) => {},
+                (Color::Black, PieceKind::Bishop) => self.get_black_bishop_moves(),
+                (Color::Black, PieceKind::Rook) => self.get_black_rook_moves(),
+                (Color::Black, PieceKind::Queen) => self.get_black_queen_moves(),
                 (Color::Black, PieceKind::King) => self.get_black_king_moves()
             };
         }
@@ -78,8 +195,9 @@ mod iter {
             ];
 
             for square in self.position.white.pawns.squares() {
+                let mask = self.move_mask(square);
                 let pawn_attacks = super::bitmask::white_pawn_attacks(square);
-                let pawn_attacks = pawn_attacks & self.position.black.all;
+                let pawn_attacks = pawn_attacks & self.position.black.all & mask;
 
                 for attacked_square in pawn_attacks.squares() {
                     if attacked_square.rank() == 7 {
@@ -92,7 +210,7 @@ mod iter {
                                 castling: None
                             });
                         }
-                    } else {
+                    } else if self.targets.is_occupied(attacked_square) {
                         self.buffer.push(Move {
                             from: square,
                             to: attacked_square,
@@ -102,29 +220,52 @@ mod iter {
                     }
                 }
 
+                if let Some(en_passant) = self.position.en_passant {
+                    let victim = en_passant_victim(en_passant, Color::White);
+                    let can_reach = super::bitmask::white_pawn_attacks(square)
+                        .is_occupied(en_passant);
+
+                    let targeted = self.targets.is_occupied(en_passant)
+                        || self.targets.is_occupied(victim);
+
+                    if can_reach && targeted && self.position.black.pawns.is_occupied(victim)
+                            && self.en_passant_is_legal(square, en_passant, victim) {
+                        self.buffer.push(Move {
+                            from: square,
+                            to: en_passant,
+                            promote_to: None,
+                            castling: None
+                        });
+                    }
+                }
+
                 if (self.position.all & (square + 8).to_bitboard()).is_empty() {
-                    if square.rank() == 6 {
-                        for promote_to in promote_pieces.iter() {
+                    // a single push may be illegal even when a double push blocks a check
+                    if mask.is_occupied(square + 8) {
+                        if square.rank() == 6 {
+                            for promote_to in promote_pieces.iter() {
+                                self.buffer.push(Move {
+                                    from: square,
+                                    to: square + 8,
+                                    promote_to: Some(*promote_to),
+                                    castling: None
+                                });
+                            }
+                        } else if self.targets.is_occupied(square + 8) {
                             self.buffer.push(Move {
                                 from: square,
                                 to: square + 8,
-                                promote_to: Some(*promote_to),
+                                promote_to: None,
                                 castling: None
                             });
                         }
-                    } else {
-                        self.buffer.push(Move {
-                            from: square,
-                            to: square + 8,
-                            promote_to: None,
-                            castling: None
-                        });
                     }
 
                     if square.rank() == 1 {
                         let two_square = (square + 16).to_bitboard();
 
-                        if (self.position.all & two_square).is_empty() {
+                        if (self.position.all & two_square).is_empty()
+                                && (mask & self.targets & two_square).is_nonempty() {
                             self.buffer.push(Move {
                                 from: square,
                                 to: square + 16,
@@ -146,8 +287,9 @@ mod iter {
             ];
 
             for square in self.position.black.pawns.squares() {
+                let mask = self.move_mask(square);
                 let pawn_attacks = super::bitmask::black_pawn_attacks(square);
-                let pawn_attacks = pawn_attacks & self.position.white.all;
+                let pawn_attacks = pawn_attacks & self.position.white.all & mask;
 
                 for attacked_square in pawn_attacks.squares() {
                     if attacked_square.rank() == 0 {
@@ -160,7 +302,7 @@ mod iter {
                                 castling: None
                             });
                         }
-                    } else {
+                    } else if self.targets.is_occupied(attacked_square) {
                         self.buffer.push(Move {
                             from: square,
                             to: attacked_square,
@@ -170,29 +312,52 @@ mod iter {
                     }
                 }
 
+                if let Some(en_passant) = self.position.en_passant {
+                    let victim = en_passant_victim(en_passant, Color::Black);
+                    let can_reach = super::bitmask::black_pawn_attacks(square)
+                        .is_occupied(en_passant);
+
+                    let targeted = self.targets.is_occupied(en_passant)
+                        || self.targets.is_occupied(victim);
+
+                    if can_reach && targeted && self.position.white.pawns.is_occupied(victim)
+                            && self.en_passant_is_legal(square, en_passant, victim) {
+                        self.buffer.push(Move {
+                            from: square,
+                            to: en_passant,
+                            promote_to: None,
+                            castling: None
+                        });
+                    }
+                }
+
                 if (self.position.all & (square - 8).to_bitboard()).is_empty() {
-                    if square.rank() == 1 {
-                        for promote_to in promote_pieces.iter() {
+                    // a single push may be illegal even when a double push blocks a check
+                    if mask.is_occupied(square - 8) {
+                        if square.rank() == 1 {
+                            for promote_to in promote_pieces.iter() {
+                                self.buffer.push(Move {
+                                    from: square,
+                                    to: square - 8,
+                                    promote_to: Some(*promote_to),
+                                    castling: None
+                                });
+                            }
+                        } else if self.targets.is_occupied(square - 8) {
                             self.buffer.push(Move {
                                 from: square,
                                 to: square - 8,
-                                promote_to: Some(*promote_to),
+                                promote_to: None,
                                 castling: None
                             });
                         }
-                    } else {
-                        self.buffer.push(Move {
-                            from: square,
-                            to: square - 8,
-                            promote_to: None,
-                            castling: None
-                        });
                     }
 
                     if square.rank() == 6 {
                         let two_square = (square - 16).to_bitboard();
 
-                        if (self.position.all & two_square).is_empty() {
+                        if (self.position.all & two_square).is_empty()
+                                && (mask & self.targets & two_square).is_nonempty() {
                             self.buffer.push(Move {
                                 from: square,
                                 to: square - 16,
@@ -209,11 +374,12 @@ mod iter {
             for from in self.position.white.knights.squares() {
                 let knight_attacks = super::bitmask::knight_moves(from);
                 let knight_attacks = knight_attacks & !self.position.white.all;
+                let knight_attacks = knight_attacks & self.move_mask(from) & self.targets;
 
                 for to in knight_attacks.squares() {
                     self.buffer.push(Move {
-                        from: from,
-                        to: to,
+                        from,
+                        to,
                         promote_to: None,
                         castling: None
                     });
@@ -225,11 +391,114 @@ mod iter {
             for from in self.position.black.knights.squares() {
                 let knight_attacks = super::bitmask::knight_moves(from);
                 let knight_attacks = knight_attacks & !self.position.black.all;
+                let knight_attacks = knight_attacks & self.move_mask(from) & self.targets;
 
                 for to in knight_attacks.squares() {
                     self.buffer.push(Move {
-                        from: from,
-                        to: to,
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_white_bishop_moves(&mut self) {
+            for from in self.position.white.bishops.squares() {
+                let bishop_attacks = self.magic.bishop_attacks(from, self.position.all);
+                let bishop_attacks = bishop_attacks & !self.position.white.all;
+                let bishop_attacks = bishop_attacks & self.move_mask(from) & self.targets;
+
+                for to in bishop_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_black_bishop_moves(&mut self) {
+            for from in self.position.black.bishops.squares() {
+                let bishop_attacks = self.magic.bishop_attacks(from, self.position.all);
+                let bishop_attacks = bishop_attacks & !self.position.black.all;
+                let bishop_attacks = bishop_attacks & self.move_mask(from) & self.targets;
+
+                for to in bishop_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_white_rook_moves(&mut self) {
+            for from in self.position.white.rooks.squares() {
+                let rook_attacks = self.magic.rook_attacks(from, self.position.all);
+                let rook_attacks = rook_attacks & !self.position.white.all;
+                let rook_attacks = rook_attacks & self.move_mask(from) & self.targets;
+
+                for to in rook_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_black_rook_moves(&mut self) {
+            for from in self.position.black.rooks.squares() {
+                let rook_attacks = self.magic.rook_attacks(from, self.position.all);
+                let rook_attacks = rook_attacks & !self.position.black.all;
+                let rook_attacks = rook_attacks & self.move_mask(from) & self.targets;
+
+                for to in rook_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_white_queen_moves(&mut self) {
+            for from in self.position.white.queens.squares() {
+                let queen_attacks = self.magic.queen_attacks(from, self.position.all);
+                let queen_attacks = queen_attacks & !self.position.white.all;
+                let queen_attacks = queen_attacks & self.move_mask(from) & self.targets;
+
+                for to in queen_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
+                        promote_to: None,
+                        castling: None
+                    });
+                }
+            }
+        }
+
+        fn get_black_queen_moves(&mut self) {
+            for from in self.position.black.queens.squares() {
+                let queen_attacks = self.magic.queen_attacks(from, self.position.all);
+                let queen_attacks = queen_attacks & !self.position.black.all;
+                let queen_attacks = queen_attacks & self.move_mask(from) & self.targets;
+
+                for to in queen_attacks.squares() {
+                    self.buffer.push(Move {
+                        from,
+                        to,
                         promote_to: None,
                         castling: None
                     });
@@ -241,44 +510,148 @@ mod iter {
             for from in self.position.white.king.squares() {
                 let king_attacks = super::bitmask::king_moves(from);
                 let king_attacks = king_attacks & !self.position.white.all;
+                let king_attacks = self.king_safe_squares(king_attacks & self.targets);
 
                 for to in king_attacks.squares() {
                     self.buffer.push(Move {
-                        from: from,
-                        to: to,
+                        from,
+                        to,
                         promote_to: None,
                         castling: None
                     });
                 }
             }
+
+            if self.position.castling.can_castle(Color::White, CastlingType::Kingside) {
+                self.get_castling_move(Color::White, 0, CastlingType::Kingside);
+            }
+
+            if self.position.castling.can_castle(Color::White, CastlingType::Queenside) {
+                self.get_castling_move(Color::White, 0, CastlingType::Queenside);
+            }
         }
 
         fn get_black_king_moves(&mut self) {
             for from in self.position.black.king.squares() {
                 let king_attacks = super::bitmask::king_moves(from);
                 let king_attacks = king_attacks & !self.position.black.all;
+                let king_attacks = self.king_safe_squares(king_attacks & self.targets);
 
                 for to in king_attacks.squares() {
                     self.buffer.push(Move {
-                        from: from,
-                        to: to,
+                        from,
+                        to,
                         promote_to: None,
                         castling: None
                     });
                 }
             }
+
+            if self.position.castling.can_castle(Color::Black, CastlingType::Kingside) {
+                self.get_castling_move(Color::Black, 7, CastlingType::Kingside);
+            }
+
+            if self.position.castling.can_castle(Color::Black, CastlingType::Queenside) {
+                self.get_castling_move(Color::Black, 7, CastlingType::Queenside);
+            }
+        }
+
+        /// Castles if the squares between the king and rook are empty, and the king doesn't start
+        /// in, pass through or land on an attacked square.
+        fn get_castling_move(&mut self, color: Color, rank: u8, castling: CastlingType) {
+            let (rook_file, to_file) = match castling {
+                CastlingType::Kingside => (7, 6),
+                CastlingType::Queenside => (0, 2)
+            };
+
+            let from = Square::from_coords(4, rank);
+            let to = Square::from_coords(to_file, rank);
+            let rook = Square::from_coords(rook_file, rank);
+
+            if !self.targets.is_occupied(to) {
+                return;
+            }
+
+            let us = self.position.get_army(color);
+            let them = self.position.get_army(color.other());
+
+            if !us.king.is_occupied(from) || !us.rooks.is_occupied(rook) {
+                return;
+            }
+
+            if (super::bitmask::between(self.magic, from, rook) & self.position.all).is_nonempty() {
+                return;
+            }
+
+            let king_path = from.to_bitboard()
+                | super::bitmask::between(self.magic, from, to)
+                | to.to_bitboard();
+
+            for square in king_path.squares() {
+                let attackers = super::attackers_to(self.position, self.magic, square,
+                                                    self.position.all);
+
+                if (attackers & them.all).is_nonempty() {
+                    return;
+                }
+            }
+
+            self.buffer.push(Move {
+                from,
+                to,
+                promote_to: None,
+                castling: Some(castling)
+            });
+        }
+    }
+
+    impl Legality {
+        fn new(position: &Position, magic: &MagicDatabase) -> Option<Legality> {
+            let us = position.get_army(position.side_to_play);
+            let them = position.get_army(position.side_to_play.other());
+            let king = us.king.squares().next()?;
+
+            let checkers = super::attackers_to(position, magic, king, position.all) & them.all;
+            let check_mask = match checkers.squares().next() {
+                None => Bitboard::new(!0),
+                Some(checker) if checkers.num_occupied_squares() == 1 => {
+                    checker.to_bitboard() | super::bitmask::between(magic, king, checker)
+                },
+                Some(_) => Bitboard::new(0)
+            };
+
+            // sliders that would be giving check if only one of our pieces weren't in the way
+            let empty = Bitboard::new(0);
+            let snipers = (magic.rook_attacks(king, empty) & (them.rooks | them.queens))
+                | (magic.bishop_attacks(king, empty) & (them.bishops | them.queens));
+
+            let mut pinned = Bitboard::new(0);
+            for sniper in snipers.squares() {
+                let blockers = super::bitmask::between(magic, king, sniper) & position.all;
+
+                if blockers.num_occupied_squares() == 1 {
+                    pinned = pinned | (blockers & us.all);
+                }
+            }
+
+            Some(Legality {
+                king,
+                check_mask,
+                pinned
+            })
         }
     }
 
     #[test]
     fn test_pawn_moves() {
-        let fen = "4b3/p2P1p1p/1P6/5P2/5p2/p6p/1P1PpP1P/8 w - - 0 1";
+        let fen = "4b2k/p2P1p1p/1P6/5P2/5p2/p6p/1P1PpP1P/K7 w - - 0 1";
         let position = Position::from_fen(fen).unwrap();
 
         // I have no good way to test this. See for youself, it's correct.
         //
         // TODO: Actual tests? Is it really worth it?
-        let iter = MovesIter::new(&position);
+        let magic = MagicDatabase::new();
+        let iter = MovesIter::new(&position, &magic);
         for motion in iter {
             println!("{}", motion.from.to_bitboard() | motion.to.to_bitboard());
         }
@@ -287,21 +660,217 @@ mod iter {
 
     #[test]
     fn test_knight_king_moves() {
-        let fen = "6p1/6k1/2n1p1P1/4P3/1p2p2p/1p2P2P/1K4N1/1P6 w - - 0 1";
+        let fen = "6b1/6k1/2n1p1P1/4P3/1p2p2p/1p2P2P/1K4N1/1N6 w - - 0 1";
         let position = Position::from_fen(fen).unwrap();
 
-        let iter = MovesIter::new(&position);
+        let magic = MagicDatabase::new();
+        let iter = MovesIter::new(&position, &magic);
         for motion in iter {
             println!("{}", motion.from.to_bitboard() | motion.to.to_bitboard());
         }
         // panic!();
     }
+
+    #[test]
+    fn test_slider_moves() {
+        let fen = "4k3/8/8/8/3p4/8/1B1Q2R1/4K3 w - - 0 1";
+        let position = Position::from_fen(fen).unwrap();
+        let magic = MagicDatabase::new();
+
+        let slider_moves: Vec<_> = MovesIter::new(&position, &magic)
+            .filter(|motion| motion.from != Square::from_san("e1"))
+            .collect();
+
+        let moves_from = |from: &str| {
+            let from = Square::from_san(from);
+            slider_moves.iter().filter(|motion| motion.from == from).count()
+        };
+
+        // the bishop's long diagonal ends by capturing on d4
+        assert_eq!(5, moves_from("b2"));
+        // the rook can't pass through the queen
+        assert_eq!(10, moves_from("g2"));
+        // the queen is hemmed in by the bishop, the rook and her own king
+        assert_eq!(14, moves_from("d2"));
+
+        let capture = slider_moves.iter()
+            .filter(|motion| motion.to == Square::from_san("d4"))
+            .count();
+        assert_eq!(2, capture);
+    }
+
+    #[cfg(test)]
+    fn legal_moves(fen: &str) -> Vec<Move> {
+        let position = Position::from_fen(fen).unwrap();
+        let magic = MagicDatabase::new();
+
+        MovesIter::legal(&position, &magic).collect()
+    }
+
+    #[test]
+    fn test_legal_check_evasions() {
+        // the king can't step back along the rank it's being checked on, and the h1 rook can't
+        // get past its own king to help
+        let moves = legal_moves("4k3/8/8/8/8/8/3P4/r3K2R w K - 0 1");
+        let destinations: Vec<_> = moves.iter().map(|motion| motion.to).collect();
+        assert_eq!(2, moves.len());
+        assert!(destinations.contains(&Square::from_san("e2")));
+        assert!(destinations.contains(&Square::from_san("f2")));
+
+        // only the double push blocks the check
+        let moves = legal_moves("4k3/8/8/8/K6r/8/4P3/8 w - - 0 1");
+        let pawn_moves: Vec<_> = moves.iter()
+            .filter(|motion| motion.from == Square::from_san("e2"))
+            .collect();
+        assert_eq!(5, moves.len());
+        assert_eq!(1, pawn_moves.len());
+        assert_eq!(Square::from_san("e4"), pawn_moves[0].to);
+    }
+
+    #[test]
+    fn test_en_passant() {
+        let capture = Move {
+            from: Square::from_san("e5"),
+            to: Square::from_san("d6"),
+            promote_to: None,
+            castling: None
+        };
+
+        assert!(legal_moves("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").contains(&capture));
+
+        // taking would take both pawns off the king's rank at once
+        assert!(!legal_moves("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 2").contains(&capture));
+
+        // taking gets rid of the pawn giving check
+        let moves = legal_moves("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        let capture = Move {
+            from: Square::from_san("e4"),
+            to: Square::from_san("d3"),
+            promote_to: None,
+            castling: None
+        };
+        assert!(moves.contains(&capture));
+    }
+
+    #[test]
+    fn test_castling() {
+        let castles = |fen: &str| -> Vec<CastlingType> {
+            legal_moves(fen).iter().filter_map(|motion| motion.castling).collect()
+        };
+
+        let both = vec![CastlingType::Queenside, CastlingType::Kingside];
+        assert_eq!(both, castles("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert_eq!(both, castles("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"));
+
+        // no rights, no castling
+        assert!(castles("r3k2r/8/8/8/8/8/8/R3K2R w kq - 0 1").is_empty());
+
+        // the knight on b1 is in the way, even though the king never crosses b1
+        let kingside = vec![CastlingType::Kingside];
+        assert_eq!(kingside, castles("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1"));
+
+        // the king may not pass through d1 or land on g1, but the rook may pass through b1
+        assert!(castles("r2rk1r1/8/8/8/8/8/8/R3K2R w KQ - 0 1").is_empty());
+        assert_eq!(both, castles("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"));
+
+        // never out of check
+        assert!(castles("r3k2r/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn test_legal_double_check() {
+        let moves = legal_moves("4r1k1/8/8/Q7/8/3n4/8/4K3 w - - 0 1");
+        assert_eq!(3, moves.len());
+        assert!(moves.iter().all(|motion| motion.from == Square::from_san("e1")));
+    }
+
+    #[test]
+    fn test_legal_pinned_pieces() {
+        // the rook may only slide along the pin, up to and including the pinning rook
+        let moves = legal_moves("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1");
+        let rook_moves: Vec<_> = moves.iter()
+            .filter(|motion| motion.from == Square::from_san("e2"))
+            .collect();
+        assert_eq!(5, rook_moves.len());
+        assert!(rook_moves.iter().all(|motion| motion.to.file() == 4));
+
+        // a knight can never move along a pin
+        let moves = legal_moves("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1");
+        assert!(moves.iter().all(|motion| motion.from == Square::from_san("e1")));
+    }
+
+    #[test]
+    fn test_targets() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"
+        ];
+
+        let magic = MagicDatabase::new();
+        for fen in fens.iter() {
+            let position = Position::from_fen(fen).unwrap();
+            let them = position.get_army(position.side_to_play.other()).all;
+
+            let captures: Vec<_> = MovesIter::legal(&position, &magic)
+                .with_targets(them)
+                .collect();
+            let expected: Vec<_> = MovesIter::legal(&position, &magic)
+                .filter(|&motion| {
+                    motion.promote_to.is_some() || position.captured_kind(motion).is_some()
+                })
+                .collect();
+            assert_eq!(expected, captures);
+
+            // everything but the moves to one square
+            let e4 = Square::from_san("e4");
+            let elsewhere: Vec<_> = MovesIter::legal(&position, &magic)
+                .with_targets(!e4.to_bitboard())
+                .collect();
+            let expected: Vec<_> = MovesIter::legal(&position, &magic)
+                .filter(|motion| motion.to != e4 || motion.promote_to.is_some())
+                .collect();
+            assert_eq!(expected, elsewhere);
+        }
+    }
 }
 
-mod bitmask {
+pub mod bitmask {
     use bitboard::Bitboard;
+    use magic::MagicDatabase;
     use square::Square;
 
+    /// The squares strictly between `a` and `b` if they share a rank, file or diagonal, and no
+    /// squares otherwise.
+    pub fn between(magic: &MagicDatabase, a: Square, b: Square) -> Bitboard {
+        let empty = Bitboard::new(0);
+
+        if magic.rook_attacks(a, empty).is_occupied(b) {
+            magic.rook_attacks(a, b.to_bitboard()) & magic.rook_attacks(b, a.to_bitboard())
+        } else if magic.bishop_attacks(a, empty).is_occupied(b) {
+            magic.bishop_attacks(a, b.to_bitboard()) & magic.bishop_attacks(b, a.to_bitboard())
+        } else {
+            empty
+        }
+    }
+
+    /// The entire line, from edge to edge, through `a` and `b` if they share a rank, file or
+    /// diagonal, and no squares otherwise.
+    pub fn line(magic: &MagicDatabase, a: Square, b: Square) -> Bitboard {
+        let empty = Bitboard::new(0);
+        let ends = a.to_bitboard() | b.to_bitboard();
+
+        if magic.rook_attacks(a, empty).is_occupied(b) {
+            (magic.rook_attacks(a, empty) & magic.rook_attacks(b, empty)) | ends
+        } else if magic.bishop_attacks(a, empty).is_occupied(b) {
+            (magic.bishop_attacks(a, empty) & magic.bishop_attacks(b, empty)) | ends
+        } else {
+            empty
+        }
+    }
+
     pub fn white_pawn_attacks(square: Square) -> Bitboard {
         let rank = square.rank() as i8;
         let file = square.file() as i8;
@@ -361,7 +930,7 @@ mod bitmask {
     }
 
     fn coords_in_bounds(file: i8, rank: i8) -> bool {
-        0 <= file && file < 8 && 0 <= rank && rank < 8
+        (0..8).contains(&file) && (0..8).contains(&rank)
     }
 
     fn add_if_in_bounds(bitboard: Bitboard, file: i8, rank: i8) -> Bitboard {
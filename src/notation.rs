@@ -0,0 +1,339 @@
+//! Reading and writing moves in Standard Algebraic Notation, the notation people use to record
+//! games: "Nbd7", "exd6", "O-O-O", "e8=Q+" and so on. Reading UCI's long algebraic notation
+//! lives here too, since it also needs the position to make sense of a move.
+
+use error::{Error, Result};
+use motion::{CastlingType, Move};
+use position::{Color, PieceKind, Position};
+use square::Square;
+
+impl Position {
+    /// Writes `motion`, which must be legal, in Standard Algebraic Notation. The moving piece's
+    /// file or rank is only given when another piece of the same kind could reach the same
+    /// square.
+    pub fn move_to_san(&self, motion: Move) -> String {
+        let mut san = String::new();
+
+        match motion.castling {
+            Some(CastlingType::Kingside) => san.push_str("O-O"),
+            Some(CastlingType::Queenside) => san.push_str("O-O-O"),
+            None => {
+                let kind = self.piece_at(motion.from).expect("no piece to move").kind;
+                let capture = self.all.is_occupied(motion.to)
+                    || (kind == PieceKind::Pawn && Some(motion.to) == self.en_passant);
+
+                if kind == PieceKind::Pawn {
+                    // pawn captures are the one case where the file is always given
+                    if capture {
+                        san.push(file_letter(motion.from));
+                    }
+                } else {
+                    san.push(piece_letter(kind));
+                    san.push_str(&self.disambiguation(motion, kind));
+                }
+
+                if capture {
+                    san.push('x');
+                }
+
+                san.push_str(&motion.to.to_string());
+
+                if let Some(promote_to) = motion.promote_to {
+                    san.push('=');
+                    san.push(piece_letter(promote_to));
+                }
+            }
+        }
+
+        let mut after = self.clone();
+        after.make_move(motion);
+
+        if after.is_in_check() {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    /// Finds the legal move that `san` describes. Besides strict SAN, this accepts castling
+    /// written with zeros, promotions without the "=", and check marks or annotations like "!?"
+    /// that are missing, superfluous or wrong.
+    pub fn parse_san(&self, san: &str) -> Result<Move> {
+        let invalid = |reason: &str| Error::InvalidSan {
+            san: san.to_string(),
+            reason: reason.to_string()
+        };
+
+        let trimmed = san.trim().trim_end_matches(|c| "+#!?".contains(c));
+        let legal_moves = self.legal_moves();
+
+        let castling = match trimmed {
+            "O-O" | "0-0" => Some(CastlingType::Kingside),
+            "O-O-O" | "0-0-0" => Some(CastlingType::Queenside),
+            _ => None
+        };
+
+        if castling.is_some() {
+            return legal_moves.into_iter()
+                .find(|motion| motion.castling == castling)
+                .ok_or_else(|| invalid("castling isn't legal here"));
+        }
+
+        let mut chars: Vec<_> = trimmed.chars().collect();
+
+        let promote_to = match chars.last().cloned().and_then(piece_from_letter) {
+            Some(PieceKind::King) => return Err(invalid("can't promote to a king")),
+            Some(kind) => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+
+                Some(kind)
+            },
+            None => None
+        };
+
+        let kind = match chars.first().cloned().and_then(piece_from_letter) {
+            Some(kind) => {
+                chars.remove(0);
+                kind
+            },
+            None => PieceKind::Pawn
+        };
+
+        if chars.len() < 2 {
+            return Err(invalid("no destination square"));
+        }
+
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = Square::parse(&to).map_err(|_| invalid("no destination square"))?;
+
+        // whatever is left says where the piece comes from, perhaps with a capture sign
+        let (mut from_file, mut from_rank) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' => from_file = Some(c as u8 - b'a'),
+                '1'..='8' => from_rank = Some(c as u8 - b'1'),
+                'x' | ':' | '-' => {},
+                _ => return Err(invalid("unexpected character"))
+            }
+        }
+
+        let mut candidates = legal_moves.into_iter().filter(|motion| {
+            motion.castling.is_none()
+                && motion.to == to
+                && motion.promote_to == promote_to
+                && self.piece_at(motion.from).map(|piece| piece.kind) == Some(kind)
+                && from_file.map_or(true, |file| motion.from.file() == file)
+                && from_rank.map_or(true, |rank| motion.from.rank() == rank)
+                // a pawn without a file is pushed, never capturing
+                && (kind != PieceKind::Pawn || from_file.is_some()
+                    || motion.from.file() == to.file())
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(motion), None) => Ok(motion),
+            (None, _) => Err(invalid("no legal move matches")),
+            (Some(_), Some(_)) => Err(invalid("more than one legal move matches"))
+        }
+    }
+
+    /// Finds the legal move that `uci` describes, like "e2e4" or "e7e8q". Whether it castles
+    /// depends on what's on the board, so it's filled in from the matching legal move. Castling
+    /// written as the king capturing its own rook, as in Chess960, is also accepted.
+    pub fn parse_uci(&self, uci: &str) -> Result<Move> {
+        let invalid = |reason: &str| Error::InvalidUci {
+            uci: uci.to_string(),
+            reason: reason.to_string()
+        };
+
+        if !uci.is_ascii() || (uci.len() != 4 && uci.len() != 5) {
+            return Err(invalid("not a move"));
+        }
+
+        let from = Square::parse(&uci[0..2]).map_err(|_| invalid("bad square"))?;
+        let mut to = Square::parse(&uci[2..4]).map_err(|_| invalid("bad square"))?;
+
+        let promote_to = match uci[4..].chars().next() {
+            Some(letter) => match piece_from_letter(letter.to_ascii_uppercase()) {
+                Some(PieceKind::King) | None => return Err(invalid("bad promotion")),
+                kind => kind
+            },
+            None => None
+        };
+
+        // only a king on its home square can take its own rook, and only if it could castle
+        // with that rook
+        let side = self.side_to_play;
+        let rank = match side {
+            Color::White => 0,
+            Color::Black => 7
+        };
+        let army = self.get_army(side);
+
+        if from == Square::from_coords(4, rank) && army.king.is_occupied(from)
+            && army.rooks.is_occupied(to) {
+            for &(castling, rook_file, king_file) in [(CastlingType::Kingside, 7, 6),
+                                                      (CastlingType::Queenside, 0, 2)].iter() {
+                if to == Square::from_coords(rook_file, rank)
+                    && self.castling.can_castle(side, castling) {
+                    to = Square::from_coords(king_file, rank);
+                }
+            }
+        }
+
+        self.legal_moves().into_iter()
+            .find(|motion| {
+                motion.from == from && motion.to == to && motion.promote_to == promote_to
+            })
+            .ok_or_else(|| invalid("no legal move matches"))
+    }
+
+    /// The file, rank or whole square needed to tell `motion` apart from moves by other pieces
+    /// of the same kind to the same square.
+    fn disambiguation(&self, motion: Move, kind: PieceKind) -> String {
+        let rivals: Vec<_> = self.legal_moves().into_iter()
+            .filter(|other| {
+                other.to == motion.to && other.from != motion.from && other.castling.is_none()
+                    && self.piece_at(other.from).map(|piece| piece.kind) == Some(kind)
+            })
+            .map(|other| other.from)
+            .collect();
+
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|rival| rival.file() != motion.from.file()) {
+            file_letter(motion.from).to_string()
+        } else if rivals.iter().all(|rival| rival.rank() != motion.from.rank()) {
+            (motion.from.rank() + 1).to_string()
+        } else {
+            motion.from.to_string()
+        }
+    }
+}
+
+fn file_letter(square: Square) -> char {
+    (b'a' + square.file()) as char
+}
+
+pub fn piece_letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::Pawn => 'P',
+        PieceKind::Knight => 'N',
+        PieceKind::Bishop => 'B',
+        PieceKind::Rook => 'R',
+        PieceKind::Queen => 'Q',
+        PieceKind::King => 'K'
+    }
+}
+
+/// The piece a capital letter stands for in SAN. Pawns don't get a letter, so 'P' isn't one.
+pub fn piece_from_letter(letter: char) -> Option<PieceKind> {
+    match letter {
+        'N' => Some(PieceKind::Knight),
+        'B' => Some(PieceKind::Bishop),
+        'R' => Some(PieceKind::Rook),
+        'Q' => Some(PieceKind::Queen),
+        'K' => Some(PieceKind::King),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+fn san_moves(fen: &str) -> Vec<String> {
+    let position = Position::from_fen(fen).unwrap();
+    let mut moves: Vec<_> = position.legal_moves().into_iter()
+        .map(|motion| position.move_to_san(motion))
+        .collect();
+
+    moves.sort();
+    moves
+}
+
+#[test]
+fn write_san() {
+    // both knights can reach d7
+    let moves = san_moves("r3k3/8/1n3n2/8/8/8/8/4K2R b q - 0 1");
+    assert!(moves.contains(&"Nbd7".to_string()));
+    assert!(moves.contains(&"Nfd7".to_string()));
+    assert!(moves.contains(&"O-O-O".to_string()));
+
+    // the queens on a1 and a5 share a file, and the ones on a1 and e1 share a rank
+    let moves = san_moves("8/7k/8/Q7/8/8/8/Q3Q1K1 w - - 0 1");
+    assert!(moves.contains(&"Qa1c3".to_string()));
+    assert!(moves.contains(&"Q5c3".to_string()));
+    assert!(moves.contains(&"Qec3".to_string()));
+    assert!(moves.contains(&"Qab1+".to_string()));
+
+    let moves = san_moves("6k1/5ppp/8/3pP3/8/8/8/R3K3 w - d6 0 1");
+    assert!(moves.contains(&"exd6".to_string()));
+    assert!(moves.contains(&"e6".to_string()));
+    assert!(moves.contains(&"Ra8#".to_string()));
+
+    let moves = san_moves("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+    assert!(moves.contains(&"a8=Q".to_string()));
+    assert!(moves.contains(&"axb8=Q+".to_string()));
+    assert!(moves.contains(&"axb8=N".to_string()));
+}
+
+#[test]
+fn read_san() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    // every move survives a round trip
+    for motion in position.legal_moves() {
+        assert_eq!(Ok(motion), position.parse_san(&position.move_to_san(motion)));
+    }
+
+    let parse = |san: &str| position.parse_san(san).unwrap();
+    assert_eq!(parse("O-O"), parse("0-0"));
+    assert_eq!(parse("O-O-O"), parse("0-0-0+!?"));
+    assert_eq!(parse("Nxf7"), parse("Ne5-f7"));
+    assert_eq!(parse("dxe6"), parse("de6"));
+
+    for bad in ["", "Ne4", "Rb2", "Ng4h2", "Pd6", "O-O-O-O", "Zf3"].iter() {
+        assert!(position.parse_san(bad).is_err(), "{:?} shouldn't parse", bad);
+    }
+
+    // the g-file pawn can't promote without saying what to
+    let position = Position::from_fen("4k3/6P1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(position.parse_san("g8=Q"), position.parse_san("g8Q"));
+    assert!(position.parse_san("g8").is_err());
+
+    // both rooks can get to d1
+    let position = Position::from_fen("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1").unwrap();
+    assert!(position.parse_san("Rd1").is_err());
+    assert!(position.parse_san("Rad1").is_ok());
+}
+
+#[test]
+fn read_uci() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    for motion in position.legal_moves() {
+        assert_eq!(Ok(motion), position.parse_uci(&motion.to_uci()));
+    }
+
+    let castle = position.parse_uci("e1g1").unwrap();
+    assert_eq!(Some(CastlingType::Kingside), castle.castling);
+    assert_eq!(Ok(castle), position.parse_uci("e1h1"));
+    assert_eq!(Some(CastlingType::Queenside), position.parse_uci("e1a1").unwrap().castling);
+
+    for bad in ["", "e2", "e2e5", "e1e3", "a2a3q", "d5d6k", "e2e4e5", "i2i4"].iter() {
+        assert!(position.parse_uci(bad).is_err(), "{:?} shouldn't parse", bad);
+    }
+
+    let position = Position::from_fen("4k3/6P1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(Some(PieceKind::Rook), position.parse_uci("g7g8r").unwrap().promote_to);
+    assert!(position.parse_uci("g7g8").is_err());
+
+    // a king next to its rook, away from home, can't castle by taking it
+    let position = Position::from_fen("7k/8/8/8/8/8/8/RK6 w - - 0 1").unwrap();
+    assert!(position.parse_uci("b1a1").is_err());
+
+    let position = Position::from_fen("k7/8/8/8/8/8/8/6KR w - - 0 1").unwrap();
+    assert!(position.parse_uci("g1h1").is_err());
+}
@@ -0,0 +1,181 @@
+//! Move ordering. Alpha-beta cuts off sooner the sooner it tries the best move, so moves get
+//! tried in order of how likely they are to be good: the move the transposition table remembers,
+//! then captures that don't lose material, then quiet moves that caused cutoffs elsewhere in the
+//! tree, and finally captures that do lose material.
+
+use motion::Move;
+use position::{Color, PieceKind, Position};
+use search::MAX_PLY;
+
+const HASH_MOVE: i32 = 3_000_000;
+const CAPTURE: i32 = 2_000_000;
+const KILLER: i32 = 1_000_000;
+const COUNTERMOVE: i32 = KILLER - 2;
+
+/// History scores stay within this much either way of zero, so that they never catch up to a
+/// killer.
+const HISTORY_MAX: i32 = 100_000;
+
+/// Promotions to anything but a queen are almost never worth trying early.
+const UNDERPROMOTION: i32 = -HISTORY_MAX - 1;
+
+/// Captures that lose material in the exchange that follows go last.
+const BAD_CAPTURE: i32 = -2 * HISTORY_MAX;
+
+/// What the search has learned about which quiet moves tend to be good.
+pub struct Heuristics {
+    /// Two quiet moves per ply that recently caused a cutoff there. Positions at the same ply
+    /// are often alike, so the same move might well work again.
+    killers: Vec<[Option<Move>; 2]>,
+    /// The quiet move that last refuted each move, by the refuted move's from and to squares.
+    countermoves: Box<[[Option<Move>; 64]; 64]>,
+    /// How often each quiet move has caused cutoffs, by side, from square and to square.
+    history: Box<[[[i32; 64]; 64]; 2]>
+}
+
+impl Default for Heuristics {
+    fn default() -> Heuristics {
+        Heuristics {
+            killers: vec![[None; 2]; MAX_PLY as usize],
+            countermoves: Box::new([[None; 64]; 64]),
+            history: Box::new([[[0; 64]; 64]; 2])
+        }
+    }
+}
+
+impl Heuristics {
+    /// Learns from `motion` causing a cutoff at `ply`, after `previous` was played to get there.
+    /// `tried` are the quiet moves that were searched before it and didn't cause one.
+    pub fn record_cutoff(&mut self, position: &Position, motion: Move, ply: u32, depth: u32,
+                         previous: Option<Move>, tried: &[Move]) {
+        if !is_quiet(position, motion) {
+            return;
+        }
+
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(motion) {
+            killers[1] = killers[0];
+            killers[0] = Some(motion);
+        }
+
+        if let Some(previous) = previous {
+            self.countermoves[previous.from.to_index() as usize]
+                [previous.to.to_index() as usize] = Some(motion);
+        }
+
+        let bonus = (depth * depth) as i32;
+        let side = position.side_to_play;
+
+        self.update_history(side, motion, bonus);
+        for &other in tried {
+            self.update_history(side, other, -bonus);
+        }
+    }
+
+    fn history(&self, side: Color, motion: Move) -> i32 {
+        self.history[side as usize][motion.from.to_index() as usize][motion.to.to_index() as usize]
+    }
+
+    fn update_history(&mut self, side: Color, motion: Move, bonus: i32) {
+        let entry = &mut self.history[side as usize][motion.from.to_index() as usize]
+            [motion.to.to_index() as usize];
+
+        // the bigger an entry already is, the less it grows, which keeps it within HISTORY_MAX
+        let bonus = bonus.clamp(-HISTORY_MAX, HISTORY_MAX);
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+/// Hands out moves best first. Rather than sorting them all up front, each call picks the best of
+/// the moves left, since a cutoff often comes before most of them are needed.
+pub struct MovePicker {
+    moves: Vec<(Move, i32)>
+}
+
+impl MovePicker {
+    pub fn new(position: &Position, moves: Vec<Move>, hash_move: Option<Move>,
+               heuristics: &Heuristics, ply: u32, previous: Option<Move>) -> MovePicker {
+        let killers = heuristics.killers.get(ply as usize).cloned().unwrap_or_default();
+        let countermove = previous.and_then(|previous| {
+            heuristics.countermoves[previous.from.to_index() as usize]
+                [previous.to.to_index() as usize]
+        });
+
+        let score = |motion: Move| {
+            if Some(motion) == hash_move {
+                return HASH_MOVE;
+            }
+
+            let attacker = position.piece_at(motion.from).unwrap().kind;
+            let victim = position.captured_kind(motion);
+
+            match (motion.promote_to, victim) {
+                (Some(PieceKind::Queen), _) => {
+                    CAPTURE + mvv_lva(PieceKind::Queen, PieceKind::Pawn)
+                        + victim.map_or(0, |victim| mvv_lva(victim, attacker))
+                },
+                (Some(_), _) => UNDERPROMOTION,
+                (None, Some(victim)) if position.see_ge(motion, 0) => {
+                    CAPTURE + mvv_lva(victim, attacker)
+                },
+                (None, Some(victim)) => BAD_CAPTURE + mvv_lva(victim, attacker),
+                _ if Some(motion) == killers[0] => KILLER,
+                _ if Some(motion) == killers[1] => KILLER - 1,
+                _ if Some(motion) == countermove => COUNTERMOVE,
+                _ => heuristics.history(position.side_to_play, motion)
+            }
+        };
+
+        MovePicker {
+            moves: moves.into_iter().map(|motion| (motion, score(motion))).collect()
+        }
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let best = (0..self.moves.len()).max_by_key(|&index| self.moves[index].1)?;
+        Some(self.moves.swap_remove(best).0)
+    }
+}
+
+/// Whether `motion` neither captures nor promotes.
+pub fn is_quiet(position: &Position, motion: Move) -> bool {
+    motion.promote_to.is_none() && position.captured_kind(motion).is_none()
+}
+
+/// Most valuable victim, least valuable attacker: taking a queen with a pawn is tried before
+/// taking a pawn with a queen.
+fn mvv_lva(victim: PieceKind, attacker: PieceKind) -> i32 {
+    8 * (victim as i32 + 1) - attacker as i32
+}
+
+#[cfg(test)]
+fn picked(position: &Position, hash_move: Option<Move>, heuristics: &Heuristics) -> Vec<String> {
+    MovePicker::new(position, position.legal_moves(), hash_move, heuristics, 0, None)
+        .map(|motion| motion.to_uci())
+        .collect()
+}
+
+#[test]
+fn picking_order() {
+    let position = Position::from_fen("4k3/8/4p3/1q1r4/2P5/8/8/3QK3 w - - 0 1").unwrap();
+    let mut heuristics = Heuristics::default();
+
+    let order = picked(&position, None, &heuristics);
+    assert_eq!(vec!["c4b5", "c4d5"], order[..2].to_vec());
+
+    // the queen taking the rook loses her once the pawns and the other queen join in
+    assert_eq!("d1d5", order[order.len() - 1]);
+
+    // the hash move goes first, and a killer goes first among the quiet moves
+    let hash_move = position.parse_uci("e1f1").ok();
+    let killer = position.parse_uci("d1a4").unwrap();
+    heuristics.record_cutoff(&position, killer, 0, 4, None, &[]);
+
+    let order = picked(&position, hash_move, &heuristics);
+    assert_eq!(vec!["e1f1", "c4b5", "c4d5", "d1a4"], order[..4].to_vec());
+    assert_eq!(position.legal_moves().len(), order.len());
+}
@@ -0,0 +1,135 @@
+use bitboard::Bitboard;
+use position::{Color, Position};
+
+/// How a game ended, as far as can be told from a single position. Draws by repetition need the
+/// game's history, so they aren't reported here.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Outcome {
+    /// `None` for a draw.
+    pub winner: Option<Color>,
+    pub reason: OutcomeReason
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutcomeReason {
+    Checkmate,
+    Stalemate,
+    /// Fifty moves by each side without a capture or pawn move. Strictly speaking, this draw has
+    /// to be claimed by one of the players.
+    FiftyMoveRule,
+    /// Seventy-five moves by each side without a capture or pawn move, which ends the game
+    /// whether or not anyone claims it.
+    SeventyFiveMoveRule,
+    /// Neither side has enough material left to ever deliver checkmate.
+    InsufficientMaterial
+}
+
+const DARK_SQUARES: Bitboard = Bitboard(0xAA55AA55AA55AA55);
+
+impl Outcome {
+    fn draw(reason: OutcomeReason) -> Outcome {
+        Outcome {
+            winner: None,
+            reason
+        }
+    }
+}
+
+impl Position {
+    /// How the game has ended, or `None` if it's still going.
+    pub fn outcome(&self) -> Option<Outcome> {
+        // checkmate takes priority over the move-count rules, even on the move that reaches them
+        if self.legal_moves().is_empty() {
+            return Some(if self.is_in_check() {
+                Outcome {
+                    winner: Some(self.side_to_play.other()),
+                    reason: OutcomeReason::Checkmate
+                }
+            } else {
+                Outcome::draw(OutcomeReason::Stalemate)
+            });
+        }
+
+        if self.halfmove_clock >= 150 {
+            Some(Outcome::draw(OutcomeReason::SeventyFiveMoveRule))
+        } else if self.halfmove_clock >= 100 {
+            Some(Outcome::draw(OutcomeReason::FiftyMoveRule))
+        } else if self.has_insufficient_material() {
+            Some(Outcome::draw(OutcomeReason::InsufficientMaterial))
+        } else {
+            None
+        }
+    }
+
+    /// Whether neither side can ever checkmate: bare kings, a single minor piece, or any number of
+    /// bishops that all live on the same color of square.
+    pub fn has_insufficient_material(&self) -> bool {
+        let (white, black) = (&self.white, &self.black);
+
+        let heavy = white.pawns | white.rooks | white.queens | black.pawns | black.rooks
+            | black.queens;
+        if heavy.is_nonempty() {
+            return false;
+        }
+
+        let knights = white.knights | black.knights;
+        let bishops = white.bishops | black.bishops;
+
+        if (knights | bishops).num_occupied_squares() <= 1 {
+            return true;
+        }
+
+        knights.is_empty() && ((bishops & DARK_SQUARES).is_empty()
+                               || (bishops & !DARK_SQUARES).is_empty())
+    }
+}
+
+#[cfg(test)]
+fn outcome(fen: &str) -> Option<Outcome> {
+    Position::from_fen(fen).unwrap().outcome()
+}
+
+#[test]
+fn checkmate_and_stalemate() {
+    let fools_mate = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+    let expected = Outcome {
+        winner: Some(Color::Black),
+        reason: OutcomeReason::Checkmate
+    };
+    assert_eq!(Some(expected), outcome(fools_mate));
+
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    assert_eq!(Some(Outcome::draw(OutcomeReason::Stalemate)), outcome(stalemate));
+
+    assert_eq!(None, outcome("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+}
+
+#[test]
+fn move_count_rules() {
+    let fifty = Some(Outcome::draw(OutcomeReason::FiftyMoveRule));
+    let seventy_five = Some(Outcome::draw(OutcomeReason::SeventyFiveMoveRule));
+
+    assert_eq!(None, outcome("4k3/8/8/8/8/8/8/R3K3 w - - 99 80"));
+    assert_eq!(fifty, outcome("4k3/8/8/8/8/8/8/R3K3 w - - 100 80"));
+    assert_eq!(seventy_five, outcome("4k3/8/8/8/8/8/8/R3K3 w - - 150 105"));
+
+    // mate on the move that reaches the limit still counts
+    let mate = outcome("R3k3/8/4K3/8/8/8/8/8 b - - 150 105").unwrap();
+    assert_eq!(OutcomeReason::Checkmate, mate.reason);
+}
+
+#[test]
+fn insufficient_material() {
+    let draw = Some(Outcome::draw(OutcomeReason::InsufficientMaterial));
+
+    assert_eq!(draw, outcome("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+    assert_eq!(draw, outcome("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"));
+    assert_eq!(draw, outcome("4kb2/8/8/8/8/8/8/4K3 w - - 0 1"));
+    // all three bishops are on light squares
+    assert_eq!(draw, outcome("4k1b1/8/8/8/8/8/8/3BKB2 w - - 0 1"));
+
+    // opposite-colored bishops, two knights and any pawn can all still mate
+    assert_eq!(None, outcome("4kb2/8/8/8/8/8/8/4KB2 w - - 0 1"));
+    assert_eq!(None, outcome("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"));
+    assert_eq!(None, outcome("4k3/8/8/8/8/8/P7/4K3 w - - 0 1"));
+}
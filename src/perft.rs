@@ -0,0 +1,216 @@
+use magic::MagicDatabase;
+use motion::Move;
+use movegen::MovesIter;
+use position::{Position, PieceKind};
+
+/// Counts of the kinds of moves made on the last ply of a perft, in the format of the tables on
+/// the Chess Programming Wiki.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PerftBreakdown {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passant: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64
+}
+
+impl Position {
+    /// Counts the leaves of the tree of legal moves `depth` plies deep.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        let magic = MagicDatabase::shared();
+        let moves: Vec<_> = MovesIter::legal(self, magic).collect();
+
+        // at the last ply, there's no need to make the moves just to count them
+        if depth <= 1 {
+            return if depth == 0 { 1 } else { moves.len() as u64 };
+        }
+
+        let mut nodes = 0;
+        for motion in moves {
+            let undo = self.make_move(motion);
+            nodes += self.perft(depth - 1);
+            self.undo_move(motion, undo);
+        }
+
+        nodes
+    }
+
+    /// Like `perft`, but reports the count under each of the legal moves from this position.
+    /// Comparing this against another move generator is the quickest way to find a bug.
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(Move, u64)> {
+        let magic = MagicDatabase::shared();
+        let moves: Vec<_> = MovesIter::legal(self, magic).collect();
+
+        moves.into_iter().map(|motion| {
+            let undo = self.make_move(motion);
+            let nodes = if depth <= 1 { 1 } else { self.perft(depth - 1) };
+            self.undo_move(motion, undo);
+
+            (motion, nodes)
+        }).collect()
+    }
+
+    /// Like `perft`, but also classifies the moves that lead to each leaf.
+    pub fn perft_breakdown(&mut self, depth: u32) -> PerftBreakdown {
+        let mut breakdown = PerftBreakdown::default();
+
+        if depth == 0 {
+            breakdown.nodes = 1;
+        } else {
+            self.perft_breakdown_into(depth, &mut breakdown);
+        }
+
+        breakdown
+    }
+
+    fn perft_breakdown_into(&mut self, depth: u32, breakdown: &mut PerftBreakdown) {
+        let magic = MagicDatabase::shared();
+        let moves: Vec<_> = MovesIter::legal(self, magic).collect();
+
+        for motion in moves {
+            let moved = self.piece_at(motion.from).unwrap();
+            let en_passant = moved.kind == PieceKind::Pawn && Some(motion.to) == self.en_passant;
+            let undo = self.make_move(motion);
+
+            if depth > 1 {
+                self.perft_breakdown_into(depth - 1, breakdown);
+            } else {
+                breakdown.nodes += 1;
+
+                if undo.captured.is_some() {
+                    breakdown.captures += 1;
+                }
+
+                if en_passant {
+                    breakdown.en_passant += 1;
+                }
+
+                if motion.castling.is_some() {
+                    breakdown.castles += 1;
+                }
+
+                if motion.promote_to.is_some() {
+                    breakdown.promotions += 1;
+                }
+
+                if self.is_in_check() {
+                    breakdown.checks += 1;
+
+                    if MovesIter::legal(self, magic).next().is_none() {
+                        breakdown.checkmates += 1;
+                    }
+                }
+            }
+
+            self.undo_move(motion, undo);
+        }
+    }
+}
+
+/// Checks a line of the EPD suite against `perft`, skipping depths with more than `max_nodes`
+/// leaves so the suite stays quick enough to run in debug builds.
+#[cfg(test)]
+fn check_epd(line_number: usize, max_nodes: u64) {
+    let suite = include_str!("../data/perft.epd");
+    let line = suite.lines().nth(line_number).unwrap();
+
+    let mut fields = line.split(';');
+    let fen = fields.next().unwrap().trim();
+    let mut position = Position::from_fen(fen).unwrap();
+
+    for field in fields {
+        let mut parts = field.split_whitespace();
+        let depth: u32 = parts.next().unwrap()[1..].parse().unwrap();
+        let expected: u64 = parts.next().unwrap().parse().unwrap();
+
+        if expected <= max_nodes {
+            assert_eq!(expected, position.perft(depth), "{} at depth {}", fen, depth);
+        }
+    }
+}
+
+#[test]
+fn perft_startpos() {
+    check_epd(0, 1_000_000);
+}
+
+#[test]
+fn perft_kiwipete() {
+    check_epd(1, 1_000_000);
+}
+
+#[test]
+fn perft_position_3() {
+    check_epd(2, 1_000_000);
+}
+
+#[test]
+fn perft_position_4() {
+    check_epd(3, 1_000_000);
+}
+
+#[test]
+fn perft_position_5() {
+    check_epd(4, 1_000_000);
+}
+
+#[test]
+fn perft_position_6() {
+    check_epd(5, 1_000_000);
+}
+
+/// Runs every line of the suite to depths of up to 20 million leaves, which is deep enough to
+/// catch what the quick tests above miss but far too slow outside of release builds.
+#[test]
+#[ignore = "slow; run with `cargo test --release -- --ignored`"]
+fn perft_deep() {
+    for line_number in 0..6 {
+        check_epd(line_number, 20_000_000);
+    }
+}
+
+#[test]
+fn perft_divide_sums_to_perft() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+
+    let divided = position.perft_divide(2);
+    assert_eq!(48, divided.len());
+    assert_eq!(position.perft(2), divided.iter().map(|&(_, nodes)| nodes).sum::<u64>());
+}
+
+#[test]
+fn perft_breakdown_startpos() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+
+    let expected = PerftBreakdown {
+        nodes: 8902,
+        captures: 34,
+        en_passant: 0,
+        castles: 0,
+        promotions: 0,
+        checks: 12,
+        checkmates: 0
+    };
+    assert_eq!(expected, position.perft_breakdown(3));
+}
+
+#[test]
+fn perft_breakdown_kiwipete() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+
+    let expected = PerftBreakdown {
+        nodes: 2039,
+        captures: 351,
+        en_passant: 1,
+        castles: 91,
+        promotions: 0,
+        checks: 3,
+        checkmates: 0
+    };
+    assert_eq!(expected, position.perft_breakdown(2));
+}
@@ -0,0 +1,100 @@
+//! Games in Portable Game Notation, the format nearly every chess program uses to store and
+//! exchange games.
+
+use std::fmt;
+
+use motion::Move;
+use position::Position;
+
+pub use self::reader::Reader;
+pub use self::writer::Writer;
+
+mod reader;
+mod writer;
+
+/// The tags every PGN game is supposed to have, in the order they're supposed to come in.
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black",
+                                         "Result"];
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    /// Every tag pair, in the order they appear in. This includes the seven-tag roster.
+    pub tags: Vec<(String, String)>,
+    /// Where the game starts, which is the usual starting position unless there's a FEN tag.
+    pub start: Position,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult
+}
+
+/// A move in a game, along with whatever annotations were made about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    pub motion: Move,
+    /// Numeric Annotation Glyphs, like 1 for "!" or 146 for "novelty".
+    pub nags: Vec<u8>,
+    /// A comment that comes before the move, which mostly happens at the start of a game or a
+    /// variation.
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    /// Lines that could have been played instead of this move. Each one starts from the
+    /// position just before it.
+    pub variations: Vec<Vec<PgnMove>>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// The game is still going, or the result isn't known.
+    Unknown
+}
+
+impl Game {
+    /// The value of the first tag called `name`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|&(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl PgnMove {
+    pub fn new(motion: Move) -> PgnMove {
+        PgnMove {
+            motion,
+            nags: vec![],
+            comment_before: None,
+            comment: None,
+            variations: vec![]
+        }
+    }
+}
+
+impl GameResult {
+    /// Reads a game termination marker, like "1-0" or "*".
+    pub fn parse(text: &str) -> Option<GameResult> {
+        match text {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let marker = match *self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*"
+        };
+
+        write!(f, "{}", marker)
+    }
+}
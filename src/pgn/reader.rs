@@ -0,0 +1,543 @@
+use std::io::BufRead;
+
+use error::{Error, Result};
+use pgn::{Game, GameResult, PgnMove, STARTING_FEN};
+use position::Position;
+
+/// Reads games from PGN text one at a time, so that only the game being read is ever held in
+/// memory. After an error, the reader skips ahead to what looks like the start of the next game.
+pub struct Reader<R> {
+    input: R,
+    bytes: Vec<u8>,
+    line: String,
+    pos: usize,
+    line_number: usize,
+    eof: bool
+}
+
+/// A line of play being read: the mainline, or a variation somewhere inside it.
+struct Line {
+    position: Position,
+    /// The position before the last move, which is where a variation on that move starts.
+    before_last: Option<Position>,
+    moves: Vec<PgnMove>,
+    /// A comment waiting for the next move to come along.
+    comment: Option<String>
+}
+
+impl Line {
+    fn new(position: Position) -> Line {
+        Line {
+            position,
+            before_last: None,
+            moves: vec![],
+            comment: None
+        }
+    }
+
+    /// Comments go after the last move, or before the first move if there isn't one yet.
+    fn add_comment(&mut self, comment: &str) {
+        let comment = comment.trim();
+        if comment.is_empty() {
+            return;
+        }
+
+        let target = match self.moves.last_mut() {
+            Some(last) if self.comment.is_none() => &mut last.comment,
+            _ => &mut self.comment
+        };
+
+        match *target {
+            Some(ref mut existing) => {
+                existing.push(' ');
+                existing.push_str(comment);
+            },
+            None => *target = Some(comment.to_string())
+        }
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader {
+            input,
+            bytes: vec![],
+            line: String::new(),
+            pos: 0,
+            line_number: 0,
+            eof: false
+        }
+    }
+
+    /// The line the reader has got up to, counting from 1.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        Error::Pgn {
+            line: self.line_number,
+            reason: reason.to_string()
+        }
+    }
+
+    /// Moves on to the next line of input. Returns false at the end of the input.
+    fn next_line(&mut self) -> Result<bool> {
+        self.bytes.clear();
+        self.line.clear();
+        self.pos = 0;
+
+        if self.eof {
+            return Ok(false);
+        }
+
+        // there's no telling what encoding a PGN file is in, so anything that isn't UTF-8 is
+        // replaced rather than treated as an error
+        match self.input.read_until(b'\n', &mut self.bytes) {
+            Ok(0) => {
+                self.eof = true;
+                Ok(false)
+            },
+            Ok(_) => {
+                self.line_number += 1;
+                self.line.push_str(&String::from_utf8_lossy(&self.bytes));
+                Ok(true)
+            },
+            Err(error) => {
+                self.eof = true;
+                Err(error.into())
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<char>> {
+        while self.pos >= self.line.len() {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+
+            // escape lines hold data for other programs
+            if self.line.starts_with('%') {
+                self.pos = self.line.len();
+            }
+        }
+
+        Ok(self.line[self.pos..].chars().next())
+    }
+
+    fn next_char(&mut self) -> Result<Option<char>> {
+        let next = self.peek()?;
+        if let Some(c) = next {
+            self.pos += c.len_utf8();
+        }
+
+        Ok(next)
+    }
+
+    fn read_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> Result<String> {
+        let mut read = String::new();
+
+        while let Some(c) = self.peek()? {
+            if !predicate(c) {
+                break;
+            }
+
+            read.push(c);
+            self.pos += c.len_utf8();
+        }
+
+        Ok(read)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<()> {
+        self.read_while(char::is_whitespace).map(|_| ())
+    }
+
+    fn read_game(&mut self) -> Result<Option<Game>> {
+        self.skip_whitespace()?;
+        if self.peek()?.is_none() {
+            return Ok(None);
+        }
+
+        let mut tags = vec![];
+        let mut start = None;
+
+        while self.peek()? == Some('[') {
+            let (name, value) = self.read_tag()?;
+
+            if name == "FEN" {
+                let position = Position::from_fen(&value)
+                    .map_err(|error| self.error(&format!("bad FEN tag: {}", error)))?;
+                start = Some(position);
+            }
+
+            tags.push((name, value));
+            self.skip_whitespace()?;
+        }
+
+        let start = start.unwrap_or_else(|| Position::from_fen(STARTING_FEN).unwrap());
+        let (moves, result) = self.read_movetext(&start)?;
+
+        Ok(Some(Game {
+            tags,
+            start,
+            moves,
+            result
+        }))
+    }
+
+    fn read_tag(&mut self) -> Result<(String, String)> {
+        self.next_char()?;
+        self.skip_whitespace()?;
+
+        let name = self.read_while(|c| c.is_alphanumeric() || c == '_')?;
+        if name.is_empty() {
+            return Err(self.error("expected a tag name"));
+        }
+
+        self.skip_whitespace()?;
+        if self.next_char()? != Some('"') {
+            return Err(self.error("expected a quoted tag value"));
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.next_char()? {
+                Some('"') => break,
+                Some('\\') => match self.next_char()? {
+                    Some(c @ '"') | Some(c @ '\\') => value.push(c),
+                    _ => return Err(self.error("bad escape in tag value"))
+                },
+                Some('\n') | Some('\r') | None => {
+                    return Err(self.error("unterminated tag value"));
+                },
+                Some(c) => value.push(c)
+            }
+        }
+
+        self.skip_whitespace()?;
+        if self.next_char()? != Some(']') {
+            return Err(self.error("expected ']' after tag value"));
+        }
+
+        Ok((name, value))
+    }
+
+    fn read_movetext(&mut self, start: &Position) -> Result<(Vec<PgnMove>, GameResult)> {
+        // the mainline is at the bottom, and the variation being read is at the top
+        let mut lines = vec![Line::new(start.clone())];
+
+        loop {
+            self.skip_whitespace()?;
+
+            let next = match self.peek()? {
+                Some(c) => c,
+                None if lines.len() == 1 => break,
+                None => return Err(self.error("unterminated variation"))
+            };
+
+            match next {
+                // the next game's tags, when this one didn't bother with a result
+                '[' if lines.len() == 1 => break,
+                '[' => return Err(self.error("unterminated variation")),
+
+                '{' => {
+                    self.next_char()?;
+                    let mut comment = String::new();
+
+                    loop {
+                        match self.next_char()? {
+                            Some('}') => break,
+                            Some(c) => comment.push(c),
+                            None => return Err(self.error("unterminated comment"))
+                        }
+                    }
+
+                    lines.last_mut().unwrap().add_comment(&comment);
+                },
+
+                ';' => {
+                    let comment = self.read_while(|c| c != '\n')?;
+                    lines.last_mut().unwrap().add_comment(&comment[1..]);
+                },
+
+                '(' => {
+                    self.next_char()?;
+
+                    let start = match lines.last().unwrap().before_last {
+                        Some(ref position) => position.clone(),
+                        None => return Err(self.error("variation before any move"))
+                    };
+
+                    lines.push(Line::new(start));
+                },
+
+                ')' => {
+                    self.next_char()?;
+
+                    if lines.len() == 1 {
+                        return Err(self.error("')' outside of a variation"));
+                    }
+
+                    let mut variation = lines.pop().unwrap();
+                    if let Some(comment) = variation.comment.take() {
+                        variation.add_comment(&comment);
+                    }
+
+                    if !variation.moves.is_empty() {
+                        let parent = lines.last_mut().unwrap();
+                        parent.moves.last_mut().unwrap().variations.push(variation.moves);
+                    }
+                },
+
+                '$' => {
+                    self.next_char()?;
+
+                    let nag = self.read_while(|c| c.is_ascii_digit())?.parse()
+                        .map_err(|_| self.error("bad annotation glyph"))?;
+                    self.annotate(&mut lines, nag)?;
+                },
+
+                '!' | '?' => {
+                    let suffix = self.read_while(|c| c == '!' || c == '?')?;
+                    let nag = self.suffix_nag(&suffix)?;
+                    self.annotate(&mut lines, nag)?;
+                },
+
+                '*' => {
+                    self.next_char()?;
+                    return self.finish(lines, GameResult::Unknown);
+                },
+
+                _ => {
+                    let symbol = self.read_while(|c| c.is_alphanumeric() || "+#=-/:".contains(c))?;
+                    if symbol.is_empty() {
+                        return Err(self.error(&format!("unexpected {:?}", next)));
+                    }
+
+                    if let Some(result) = GameResult::parse(&symbol) {
+                        return self.finish(lines, result);
+                    }
+
+                    // move numbers are only there for people to read
+                    if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        self.read_while(|c| c == '.')?;
+                        continue;
+                    }
+
+                    let suffix = self.read_while(|c| c == '!' || c == '?')?;
+
+                    let line = lines.last_mut().unwrap();
+                    let motion = line.position.parse_san(&symbol)
+                        .map_err(|error| self.error(&error.to_string()))?;
+
+                    let mut pgn_move = PgnMove::new(motion);
+                    pgn_move.comment_before = line.comment.take();
+                    if !suffix.is_empty() {
+                        pgn_move.nags.push(self.suffix_nag(&suffix)?);
+                    }
+
+                    line.before_last = Some(line.position.clone());
+                    line.position.make_move(motion);
+                    line.moves.push(pgn_move);
+                }
+            }
+        }
+
+        self.finish(lines, GameResult::Unknown)
+    }
+
+    fn finish(&self, mut lines: Vec<Line>, result: GameResult)
+              -> Result<(Vec<PgnMove>, GameResult)> {
+        if lines.len() > 1 {
+            return Err(self.error("result inside a variation"));
+        }
+
+        Ok((lines.pop().unwrap().moves, result))
+    }
+
+    fn annotate(&self, lines: &mut [Line], nag: u8) -> Result<()> {
+        match lines.last_mut().unwrap().moves.last_mut() {
+            Some(last) => {
+                last.nags.push(nag);
+                Ok(())
+            },
+            None => Err(self.error("annotation before any move"))
+        }
+    }
+
+    /// The glyph for a move suffix like "!?".
+    fn suffix_nag(&self, suffix: &str) -> Result<u8> {
+        match suffix {
+            "!" => Ok(1),
+            "?" => Ok(2),
+            "!!" => Ok(3),
+            "??" => Ok(4),
+            "!?" => Ok(5),
+            "?!" => Ok(6),
+            _ => Err(self.error(&format!("unknown move suffix {:?}", suffix)))
+        }
+    }
+
+    /// Skips to the next line starting with a tag that follows a blank line, which is most
+    /// likely where the next game starts.
+    fn skip_to_next_game(&mut self) {
+        if self.pos == 0 && self.line.starts_with('[') {
+            return;
+        }
+
+        let mut blank = false;
+        loop {
+            match self.next_line() {
+                Ok(true) => {},
+                Ok(false) | Err(_) => return
+            }
+
+            if blank && self.line.starts_with('[') {
+                return;
+            }
+
+            blank = self.line.trim().is_empty();
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Game>;
+
+    fn next(&mut self) -> Option<Result<Game>> {
+        match self.read_game() {
+            Ok(game) => game.map(Ok),
+            Err(error) => {
+                self.skip_to_next_game();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_games(pgn: &str) -> Vec<Result<Game>> {
+    Reader::new(pgn.as_bytes()).collect()
+}
+
+#[cfg(test)]
+fn san_line(start: &Position, moves: &[PgnMove]) -> Vec<String> {
+    let mut position = start.clone();
+
+    moves.iter().map(|pgn_move| {
+        let san = position.move_to_san(pgn_move.motion);
+        position.make_move(pgn_move.motion);
+        san
+    }).collect()
+}
+
+#[test]
+fn read_annotated_game() {
+    let pgn = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+[Annotator "Someone \"quoted\""]
+
+{The opening} 1. e4 e5 2. Nf3 $1 Nc6 3. Bb5 a6!? ; the Morphy Defence
+4. Ba4 (4. Bxc6 dxc6 (4... bxc6?) 5. O-O {is the Exchange Variation}) 4... Nf6
+5. 0-0 Be7 1/2-1/2
+"#;
+
+    let games = read_games(pgn);
+    assert_eq!(1, games.len());
+
+    let game = games[0].as_ref().unwrap();
+    assert_eq!(8, game.tags.len());
+    assert_eq!(Some("Fischer, Robert J."), game.tag("White"));
+    assert_eq!(Some("Someone \"quoted\""), game.tag("Annotator"));
+    assert_eq!(GameResult::Draw, game.result);
+
+    let mainline = ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O", "Be7"];
+    assert_eq!(mainline.to_vec(), san_line(&game.start, &game.moves));
+
+    assert_eq!(Some("The opening".to_string()), game.moves[0].comment_before);
+    assert_eq!(vec![1], game.moves[2].nags);
+    assert_eq!(vec![5], game.moves[5].nags);
+    assert_eq!(Some("the Morphy Defence".to_string()), game.moves[5].comment);
+
+    // the variation replaces 4. Ba4, and has a variation of its own on 4... dxc6
+    let mut position = game.start.clone();
+    for pgn_move in &game.moves[..6] {
+        position.make_move(pgn_move.motion);
+    }
+
+    let variation = &game.moves[6].variations[0];
+    assert_eq!(vec!["Bxc6", "dxc6", "O-O"], san_line(&position, variation));
+    assert_eq!(Some("is the Exchange Variation".to_string()), variation[2].comment);
+    assert_eq!(vec![2], variation[1].variations[0][0].nags);
+}
+
+#[test]
+fn read_several_games() {
+    let pgn = r#"[Event "First"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"]
+
+1. O-O-O Kf7 *
+
+[Event "Second"]
+
+1. d4 d5 2. c4 0-1
+% an escape line, which gets ignored
+[Event "Third"]
+
+1. e4
+"#;
+
+    let games: Vec<_> = read_games(pgn).into_iter().map(Result::unwrap).collect();
+    assert_eq!(3, games.len());
+
+    assert_eq!(vec!["O-O-O", "Kf7"], san_line(&games[0].start, &games[0].moves));
+    assert_eq!(GameResult::Unknown, games[0].result);
+    assert_eq!(GameResult::BlackWins, games[1].result);
+    assert_eq!(3, games[1].moves.len());
+
+    // a missing result is tolerated at the end of the input
+    assert_eq!(Some("Third"), games[2].tag("Event"));
+    assert_eq!(1, games[2].moves.len());
+}
+
+#[test]
+fn read_errors() {
+    let pgn = r#"[Event "Broken"]
+
+1. e4 e5
+2. Nf4 Nc6 1-0
+
+[Event "Unbalanced"]
+
+1. e4 (1. d4 d5 2. c4 *
+
+[Event "Fine"]
+
+1. e4 1-0
+"#;
+
+    let games = read_games(pgn);
+    assert_eq!(3, games.len());
+
+    match games[0] {
+        Err(Error::Pgn { line, .. }) => assert_eq!(4, line),
+        ref other => panic!("expected an error, got {:?}", other)
+    }
+
+    match games[1] {
+        Err(Error::Pgn { line, ref reason }) => {
+            assert_eq!(8, line);
+            assert_eq!("result inside a variation", reason);
+        },
+        ref other => panic!("expected an error, got {:?}", other)
+    }
+
+    assert_eq!(Some("Fine"), games[2].as_ref().unwrap().tag("Event"));
+}
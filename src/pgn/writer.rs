@@ -0,0 +1,239 @@
+use std::io::Write;
+
+use error::Result;
+use pgn::{Game, PgnMove, SEVEN_TAG_ROSTER, STARTING_FEN};
+use position::{Color, Position};
+
+/// The longest a line of PGN is allowed to get.
+const LINE_WIDTH: usize = 80;
+
+/// Writes games out as PGN, in the export format the standard describes.
+pub struct Writer<W> {
+    out: W
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Writer<W> {
+        Writer { out }
+    }
+
+    /// Writes `game`, followed by the blank line that separates it from the next one.
+    pub fn write_game(&mut self, game: &Game) -> Result<()> {
+        writeln!(self.out, "{}", game.to_pgn())?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl Game {
+    /// Formats the game as PGN. The seven-tag roster always comes first, filled in with "?"
+    /// where it's missing, and a game that doesn't start from the usual position gets SetUp and
+    /// FEN tags describing where it does start.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for &name in SEVEN_TAG_ROSTER.iter() {
+            let value = match name {
+                "Result" => self.result.to_string(),
+                "Date" => self.tag(name).unwrap_or("????.??.??").to_string(),
+                _ => self.tag(name).unwrap_or("?").to_string()
+            };
+
+            push_tag(&mut pgn, name, &value);
+        }
+
+        let fen = self.start.to_fen();
+        if fen != STARTING_FEN {
+            push_tag(&mut pgn, "SetUp", "1");
+            push_tag(&mut pgn, "FEN", &fen);
+        }
+
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+                push_tag(&mut pgn, name, value);
+            }
+        }
+
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        push_line(&mut tokens, &self.start, &self.moves);
+        tokens.push(self.result.to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > LINE_WIDTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+
+            pgn.push_str(&token);
+            line_length += token.len();
+        }
+
+        pgn.push('\n');
+        pgn
+    }
+}
+
+fn push_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+/// Adds the tokens for a line of play starting from `position`. Black's moves get a move number
+/// wherever the flow of moves was interrupted: at the start of a line, and after a comment or
+/// a variation.
+fn push_line(tokens: &mut Vec<String>, position: &Position, moves: &[PgnMove]) {
+    let mut position = position.clone();
+    let mut interrupted = true;
+
+    for pgn_move in moves {
+        if let Some(ref comment) = pgn_move.comment_before {
+            interrupted |= push_comment(tokens, comment);
+        }
+
+        match position.side_to_play {
+            Color::White => tokens.push(format!("{}.", position.fullmove_number)),
+            Color::Black if interrupted => tokens.push(format!("{}...", position.fullmove_number)),
+            Color::Black => {}
+        }
+
+        tokens.push(position.move_to_san(pgn_move.motion));
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        interrupted = false;
+
+        if let Some(ref comment) = pgn_move.comment {
+            interrupted |= push_comment(tokens, comment);
+        }
+
+        for variation in &pgn_move.variations {
+            if variation.is_empty() {
+                continue;
+            }
+
+            let first = tokens.len();
+            push_line(tokens, &position, variation);
+
+            tokens[first].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+            interrupted = true;
+        }
+
+        position.make_move(pgn_move.motion);
+    }
+}
+
+/// Adds a comment a word at a time, so that it can be wrapped like everything else. Returns
+/// whether there was anything to add.
+fn push_comment(tokens: &mut Vec<String>, comment: &str) -> bool {
+    // there's no way to escape a brace inside a comment
+    let comment = comment.replace('}', ")");
+    let first = tokens.len();
+
+    tokens.extend(comment.split_whitespace().map(str::to_string));
+    if tokens.len() == first {
+        return false;
+    }
+
+    tokens[first].insert(0, '{');
+    tokens.last_mut().unwrap().push('}');
+    true
+}
+
+#[cfg(test)]
+fn parse_moves(position: &Position, sans: &[&str]) -> Vec<PgnMove> {
+    let mut position = position.clone();
+
+    sans.iter().map(|san| {
+        let motion = position.parse_san(san).unwrap();
+        position.make_move(motion);
+        PgnMove::new(motion)
+    }).collect()
+}
+
+#[test]
+fn write_game() {
+    use pgn::GameResult;
+
+    let start = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+    let mut moves = parse_moves(&start, &["Kd7", "e4", "Ke6", "Kf2", "Ke5", "Kf3"]);
+
+    moves[1].nags.push(1);
+    moves[1].comment = Some("the only \"winning\" try".to_string());
+
+    let mut after_e4 = start.clone();
+    after_e4.make_move(moves[0].motion);
+    after_e4.make_move(moves[1].motion);
+    let mut alternative = parse_moves(&after_e4, &["Ke7", "Kd2"]);
+    alternative[0].comment_before = Some("or".to_string());
+    moves[2].variations.push(alternative);
+
+    let game = Game {
+        tags: vec![
+            ("White".to_string(), "A. \"Player\"".to_string()),
+            ("Opening".to_string(), "King and pawn".to_string()),
+            ("Event".to_string(), "Endgame study".to_string())
+        ],
+        start,
+        moves,
+        result: GameResult::Draw
+    };
+
+    let expected = r#"[Event "Endgame study"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "A. \"Player\""]
+[Black "?"]
+[Result "1/2-1/2"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"]
+[Opening "King and pawn"]
+
+40... Kd7 41. e4 $1 {the only "winning" try} 41... Ke6 ({or} 41... Ke7 42. Kd2)
+42. Kf2 Ke5 43. Kf3 1/2-1/2
+"#;
+
+    assert_eq!(expected, game.to_pgn());
+}
+
+#[test]
+fn write_read_round_trip() {
+    use pgn::Reader;
+
+    let pgn = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.} 3... a6 4.
+Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 11. c4 c6
+12. cxb5 axb5 13. Nc3 Bb7 14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5 Nxe4 18.
+Bxe7 Qxe7 19. exd6 Qf6 20. Nbd2 Nxd6 21. Nc4 Nxc4 22. Bxc4 Nb6 23. Ne5 Rae8 24.
+Bxf7+ Rxf7 25. Nxf7 Rxe1+ 26. Qxe1 Kxf7 27. Qe3 Qg5 28. Qxg5 hxg5 29. b3 Ke6 30.
+a3 Kd6 31. axb4 cxb4 32. Ra5 Nd5 33. f3 Bc8 34. Kf2 Bf5 35. Ra7 g6 36. Ra6+ Kc5
+37. Ke1 Nf4 38. g3 Nxh3 39. Kd2 Kb5 40. Rd6 Kc5 41. Ra6 Nf2 42. g4 Bd3 43. Re6
+1/2-1/2
+"#;
+
+    let game = Reader::new(pgn.as_bytes()).next().unwrap().unwrap();
+    assert_eq!(pgn, game.to_pgn());
+
+    let mut writer = Writer::new(vec![]);
+    writer.write_game(&game).unwrap();
+    writer.write_game(&game).unwrap();
+
+    let written = writer.into_inner();
+    let games: Vec<_> = Reader::new(&written[..]).map(Result::unwrap).collect();
+    assert_eq!(vec![game.clone(), game], games);
+}
@@ -2,8 +2,13 @@ use std::fmt;
 
 use fen;
 use bitboard::Bitboard;
+use castling::CastlingRights;
+use error::{self, Error, FenField};
+use magic::MagicDatabase;
+use movegen::{self, MovesIter};
 use square::Square;
 use motion::{CastlingType, Move};
+use zobrist;
 
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct Position {
@@ -12,19 +17,23 @@ pub struct Position {
     pub all: Bitboard,
 
     pub side_to_play: Color,
-    pub white_can_oo: bool,
-    pub white_can_ooo: bool,
-    pub black_can_oo: bool,
-    pub black_can_ooo: bool,
+    pub castling: CastlingRights,
     pub en_passant: Option<Square>,
     pub halfmove_clock: u64,
-    pub fullmove_number: u64
+    pub fullmove_number: u64,
+
+    /// A Zobrist hash of the position, for transposition tables and spotting repetitions. The
+    /// move counters don't contribute to it.
+    pub hash: u64
 }
 
 impl Position {
-    pub fn from_fen(fen: &str) -> fen::FenResult<Position> {
+    /// Parses a position from Forsyth-Edwards Notation, rejecting positions that `validate`
+    /// wouldn't accept.
+    pub fn from_fen(fen: &str) -> error::Result<Position> {
         let mut position = Position::default();
-        let fen_board = try!(fen::BoardState::from_fen(fen));
+        let fen_board = fen::BoardState::from_fen(fen)
+            .map_err(|error| Error::from_fen_error(fen, error))?;
 
         for i in 0..64 {
             match fen_board.pieces[i] {
@@ -45,7 +54,7 @@ impl Position {
                     };
 
                     let square_bitboard = Square::new(i as u8).to_bitboard();
-                    *bitboard = bitboard.clone() | square_bitboard;
+                    *bitboard = *bitboard | square_bitboard;
                 }
             }
         }
@@ -55,23 +64,147 @@ impl Position {
             fen::Color::Black => Color::Black
         };
 
-        position.white_can_oo = fen_board.white_can_oo;
-        position.white_can_ooo = fen_board.white_can_ooo;
-        position.black_can_oo = fen_board.black_can_oo;
-        position.black_can_ooo = fen_board.black_can_ooo;
-        position.en_passant = match fen_board.en_passant_square {
-            None => None,
-            Some(square) => Some(Square::new(square))
-        };
+        let flags = [
+            (fen_board.white_can_oo, CastlingRights::WHITE_OO),
+            (fen_board.white_can_ooo, CastlingRights::WHITE_OOO),
+            (fen_board.black_can_oo, CastlingRights::BLACK_OO),
+            (fen_board.black_can_ooo, CastlingRights::BLACK_OOO)
+        ];
+
+        for &(flag, right) in flags.iter() {
+            if flag {
+                position.castling.insert(right);
+            }
+        }
+
+        position.en_passant = fen_board.en_passant_square.map(Square::new);
 
         position.halfmove_clock = fen_board.halfmove_clock;
         position.fullmove_number = fen_board.fullmove_number;
 
         position.update_special_bitboards();
+        position.hash = zobrist::hash(&position);
 
+        position.validate()?;
         Ok(position)
     }
 
+    /// Checks for setups that can't come up in a game, and that the rest of the crate assumes
+    /// never happen: each side has one king, no pawns are on the back ranks, and the side that
+    /// just moved isn't in check. The castling rights and en passant square also have to agree
+    /// with where the pieces are.
+    pub fn validate(&self) -> error::Result<()> {
+        let invalid = |reason: &str| Err(Error::InvalidPosition(reason.to_string()));
+
+        if self.white.king.num_occupied_squares() != 1
+            || self.black.king.num_occupied_squares() != 1 {
+            return invalid("each side must have exactly one king");
+        }
+
+        let back_ranks = Bitboard(0xFF000000000000FF);
+        if ((self.white.pawns | self.black.pawns) & back_ranks).is_nonempty() {
+            return invalid("pawns can't be on the first or last rank");
+        }
+
+        let waiting = self.side_to_play.other();
+        let waiting_king = self.get_army(waiting).king.squares().next().unwrap();
+        if self.is_attacked_by(waiting_king, self.side_to_play) {
+            return invalid("the side that just moved is in check");
+        }
+
+        for &color in [Color::White, Color::Black].iter() {
+            let rank = match color {
+                Color::White => 0,
+                Color::Black => 7
+            };
+
+            let army = self.get_army(color);
+            let king_home = Square::from_coords(4, rank);
+
+            for &(castling, rook_file) in [(CastlingType::Kingside, 7),
+                                           (CastlingType::Queenside, 0)].iter() {
+                let rook_home = Square::from_coords(rook_file, rank);
+
+                if self.castling.can_castle(color, castling)
+                    && !(army.king.is_occupied(king_home) && army.rooks.is_occupied(rook_home)) {
+                    return Err(Error::InvalidFen {
+                        field: FenField::Castling,
+                        reason: "the king or rook has left its starting square".to_string()
+                    });
+                }
+            }
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            let (rank, origin_rank, pushed) = match self.side_to_play {
+                Color::White => (5, 6, self.black.pawns),
+                Color::Black => (2, 1, self.white.pawns)
+            };
+            let victim = en_passant_victim(en_passant, self.side_to_play);
+            let origin = Square::from_coords(en_passant.file(), origin_rank);
+
+            if en_passant.rank() != rank || !pushed.is_occupied(victim)
+                || self.all.is_occupied(en_passant) || self.all.is_occupied(origin) {
+                return Err(Error::InvalidFen {
+                    field: FenField::EnPassant,
+                    reason: "no pawn has just made a double push past it".to_string()
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the position out in Forsyth-Edwards Notation, including the en passant square
+    /// whenever the last move was a double pawn push.
+    pub fn to_fen(&self) -> String {
+        self.to_fen_with(EnPassantMode::Always)
+    }
+
+    pub fn to_fen_with(&self, en_passant_mode: EnPassantMode) -> String {
+        let pieces = (0..64).map(|i| {
+            self.piece_at(Square::new(i)).map(|piece| {
+                let color = match piece.color {
+                    Color::White => fen::Color::White,
+                    Color::Black => fen::Color::Black
+                };
+
+                let kind = match piece.kind {
+                    PieceKind::Pawn => fen::PieceKind::Pawn,
+                    PieceKind::Knight => fen::PieceKind::Knight,
+                    PieceKind::Bishop => fen::PieceKind::Bishop,
+                    PieceKind::Rook => fen::PieceKind::Rook,
+                    PieceKind::Queen => fen::PieceKind::Queen,
+                    PieceKind::King => fen::PieceKind::King
+                };
+
+                fen::Piece { kind, color }
+            })
+        }).collect();
+
+        let en_passant = match en_passant_mode {
+            EnPassantMode::Always => self.en_passant,
+            EnPassantMode::Legal => self.capturable_en_passant()
+        };
+
+        let fen_board = fen::BoardState {
+            pieces,
+            side_to_play: match self.side_to_play {
+                Color::White => fen::Color::White,
+                Color::Black => fen::Color::Black
+            },
+            white_can_oo: self.castling.contains(CastlingRights::WHITE_OO),
+            white_can_ooo: self.castling.contains(CastlingRights::WHITE_OOO),
+            black_can_oo: self.castling.contains(CastlingRights::BLACK_OO),
+            black_can_ooo: self.castling.contains(CastlingRights::BLACK_OOO),
+            en_passant_square: en_passant.map(|square| square.to_index()),
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number
+        };
+
+        fen_board.to_fen()
+    }
+
     pub fn piece_at(&self, square: Square) -> Option<Piece> {
         let bitboard = square.to_bitboard();
 
@@ -104,18 +237,41 @@ impl Position {
         }
     }
 
+    /// Makes `motion`, which must be legal. Use `try_make_move` for moves that might not be.
     pub fn make_move(&mut self, motion: Move) -> UndoContext {
         let from = self.piece_at(motion.from).unwrap();
-        let captured = self.piece_at(motion.to);
 
-        let mut undo = UndoContext {
+        // a pawn moving onto the en passant square is always an en passant capture, since
+        // nothing else can be standing there
+        let en_passant_capture = from.kind == PieceKind::Pawn
+            && Some(motion.to) == self.en_passant;
+        let captured_square = if en_passant_capture {
+            en_passant_victim(motion.to, self.side_to_play)
+        } else {
+            motion.to
+        };
+        let captured = self.piece_at(captured_square);
+
+        let undo = UndoContext {
             halfmove_clock: self.halfmove_clock,
             captured: captured.map(|piece| piece.kind),
             en_passant: self.en_passant,
-            reset_oo: false,
-            reset_ooo: false
+            en_passant_capture,
+            castling: self.castling,
+            hash: self.hash
         };
 
+        // take out the parts of the hash that aren't tied to a piece, and put them back in once
+        // the move is done
+        self.hash ^= zobrist::castling(self.castling)
+            ^ zobrist::en_passant(self.capturable_en_passant());
+
+        // the en passant square only lasts for one move
+        self.en_passant = None;
+
+        // moving a king or rook, or capturing a rook, on its home square gives up castling there
+        self.castling = self.castling.after_move(motion.from, motion.to);
+
         // update half-move counter -- this is done early so that the move being performed can
         // reset it to zero later
         match from.kind {
@@ -129,47 +285,34 @@ impl Position {
         }
 
         // update full-move number
-        match self.side_to_play {
-            Color::Black => {
-                self.fullmove_number += 1;
-            },
-
-            _ => {}
+        if self.side_to_play == Color::Black {
+            self.fullmove_number += 1;
         }
 
+        // the occupancy bitboards are all kept up to date by XOR-ing pieces on and off, so a
+        // capture briefly clears the square the capturing piece lands on
         if let Some(promote_to) = motion.promote_to {
-            let army = match self.side_to_play {
-                Color::White => &mut self.white,
-                Color::Black => &mut self.black
-            };
+            let promoted = Piece::new(from.color, promote_to);
 
-            let pawn_bitmask = motion.from.to_bitboard();
-            army.pawns = army.pawns ^ pawn_bitmask;
+            self.toggle(from, motion.from.to_bitboard());
+            self.toggle(promoted, motion.to.to_bitboard());
 
-            let promo_bitboard = army.get_bitboard_mut(promote_to);
-            let promo_bitmask = motion.to.to_bitboard();
-            *promo_bitboard = promo_bitboard.clone() | promo_bitmask;
+            self.hash ^= zobrist::piece(from, motion.from) ^ zobrist::piece(promoted, motion.to);
         } else {
             // change the bitboard of the moving piece
-            let bitboard = self.get_bitboard_mut(from.clone());
-            let bitmask = motion.from.to_bitboard() | motion.to.to_bitboard();
+            self.toggle(from, motion.from.to_bitboard() | motion.to.to_bitboard());
 
-            *bitboard = bitboard.clone() ^ bitmask;
+            self.hash ^= zobrist::piece(from, motion.from) ^ zobrist::piece(from, motion.to);
         }
 
         // change the bitboard of any piece being captured
-        match captured {
-            Some(to) => {
-                self.halfmove_clock = 0;
-
-                let bitboard = self.get_bitboard_mut(to);
-                let bitmask = motion.to.to_bitboard();
+        if let Some(to) = captured {
+            self.halfmove_clock = 0;
 
-                *bitboard = bitboard.clone() ^ bitmask;
-            },
+            self.toggle(to, captured_square.to_bitboard());
 
-            None => {}
-        };
+            self.hash ^= zobrist::piece(to, captured_square);
+        }
 
         match from.kind {
             PieceKind::Pawn => {
@@ -187,65 +330,14 @@ impl Position {
                 }
             },
 
-            PieceKind::Rook => {
-                // handle updating castling rights
-                match self.side_to_play {
-                    Color::White => {
-                        if motion.from == Square::from_san("h1") {
-                            self.white_can_oo = false;
-                            undo.reset_oo = true;
-                        }
-
-                        if motion.from == Square::from_san("a1") {
-                            self.white_can_ooo = false;
-                            undo.reset_ooo = true;
-                        }
-                    },
-
-                    Color::Black => {
-                        if motion.from == Square::from_san("h8") {
-                            self.black_can_oo = false;
-                            undo.reset_oo = true;
-                        }
-
-                        if motion.from == Square::from_san("a8") {
-                            self.black_can_ooo = false;
-                            undo.reset_ooo = true;
-                        }
-                    }
-                }
-            },
-
             PieceKind::King => {
-                self.white_can_oo = false;
-                self.white_can_ooo = false;
-                undo.reset_oo = true;
-                undo.reset_ooo = true;
-
                 if let Some(castling_type) = motion.castling {
-                    match self.side_to_play {
-                        Color::White => {
-                            let bitmask = match castling_type {
-                                CastlingType::Kingside => Square::from_san("h1").to_bitboard()
-                                        | Square::from_san("f1").to_bitboard(),
-                                CastlingType::Queenside => Square::from_san("a1").to_bitboard()
-                                        | Square::from_san("d1").to_bitboard()
-                            };
-
-                            self.white.rooks = self.white.rooks ^ bitmask;
-                        },
-
-                        Color::Black => {
-                            let bitmask = match castling_type {
-                                CastlingType::Kingside => Square::from_san("h8").to_bitboard()
-                                    | Square::from_san("f8").to_bitboard(),
-                                CastlingType::Queenside => Square::from_san("a8").to_bitboard()
-                                        | Square::from_san("d8").to_bitboard()
-                            };
-
-                            self.black.rooks = self.black.rooks ^ bitmask;
-                        }
-                    }
+                    let (rook_from, rook_to) = castling_rook_squares(motion.from, castling_type);
+                    let rook = Piece::new(from.color, PieceKind::Rook);
+
+                    self.toggle(rook, rook_from.to_bitboard() | rook_to.to_bitboard());
+
+                    self.hash ^= zobrist::piece(rook, rook_from) ^ zobrist::piece(rook, rook_to);
                 }
             },
 
@@ -258,88 +350,69 @@ impl Position {
             Color::Black => Color::White
         };
 
+        self.hash ^= zobrist::castling(self.castling)
+            ^ zobrist::en_passant(self.capturable_en_passant())
+            ^ zobrist::side_to_play(Color::White)
+            ^ zobrist::side_to_play(Color::Black);
+        debug_assert_eq!(zobrist::hash(self), self.hash, "hash out of date after {:?}", motion);
+        debug_assert!(self.occupancy_is_consistent(), "occupancy out of date after {:?}", motion);
+
         undo
     }
 
-    pub fn undo_move(&mut self, motion: Move, undo: UndoContext) {
-        let to = self.piece_at(motion.to).unwrap();
+    /// Makes `motion` if it's a legal move in this position, and otherwise leaves the position
+    /// untouched.
+    pub fn try_make_move(&mut self, motion: Move) -> error::Result<UndoContext> {
+        if self.piece_at(motion.from).is_none() {
+            return Err(Error::NoPieceOnSquare(motion.from));
+        }
 
-        if let Some(promote_to) = motion.promote_to {
-            let army = match self.side_to_play {
-                Color::White => &mut self.black,
-                Color::Black => &mut self.white
-            };
+        if !self.legal_moves().contains(&motion) {
+            return Err(Error::IllegalMove(motion));
+        }
 
-            let pawn_bitmask = motion.from.to_bitboard();
-            army.pawns = army.pawns ^ pawn_bitmask;
+        Ok(self.make_move(motion))
+    }
 
-            let promo_bitboard = army.get_bitboard_mut(promote_to);
-            let promo_bitmask = motion.to.to_bitboard();
+    pub fn undo_move(&mut self, motion: Move, undo: UndoContext) {
+        let to = self.piece_at(motion.to).unwrap();
 
-            *promo_bitboard = promo_bitboard.clone() ^ promo_bitmask;
+        if motion.promote_to.is_some() {
+            let pawn = Piece::new(to.color, PieceKind::Pawn);
+
+            self.toggle(to, motion.to.to_bitboard());
+            self.toggle(pawn, motion.from.to_bitboard());
         } else {
             // change the bitboard of the moving piece
-            let bitboard = self.get_bitboard_mut(to.clone());
-            let bitmask = motion.from.to_bitboard() | motion.to.to_bitboard();
-
-            *bitboard = bitboard.clone() ^ bitmask;
+            self.toggle(to, motion.from.to_bitboard() | motion.to.to_bitboard());
         }
 
         if let Some(captured) = undo.captured {
             let side = self.side_to_play;
-            let bitboard = self.get_army_mut(side).get_bitboard_mut(captured);
-            let bitmask = motion.to.to_bitboard();
-
-            *bitboard = bitboard.clone() ^ bitmask;
-        };
-
-        if undo.reset_oo {
-            match self.side_to_play {
-                Color::White => self.black_can_oo = true,
-                Color::Black => self.white_can_oo = true
+            let captured_square = if undo.en_passant_capture {
+                en_passant_victim(motion.to, side.other())
+            } else {
+                motion.to
             };
-        }
 
-        if undo.reset_ooo {
-            match self.side_to_play {
-                Color::White => self.black_can_ooo = true,
-                Color::Black => self.white_can_ooo = true
-            };
-        }
+            self.toggle(Piece::new(side, captured), captured_square.to_bitboard());
+        };
 
         if let Some(castling_type) = motion.castling {
-            match self.side_to_play {
-                Color::White => {
-                    let bitmask = match castling_type {
-                        CastlingType::Kingside => Square::from_san("h8").to_bitboard()
-                            | Square::from_san("f8").to_bitboard(),
-                        CastlingType::Queenside => Square::from_san("a8").to_bitboard()
-                                | Square::from_san("d8").to_bitboard()
-                    };
-
-                    self.black.rooks = self.black.rooks ^ bitmask;
-                },
+            let (rook_from, rook_to) = castling_rook_squares(motion.from, castling_type);
+            let rook = Piece::new(to.color, PieceKind::Rook);
 
-                Color::Black => {
-                    let bitmask = match castling_type {
-                        CastlingType::Kingside => Square::from_san("h1").to_bitboard()
-                                | Square::from_san("f1").to_bitboard(),
-                        CastlingType::Queenside => Square::from_san("a1").to_bitboard()
-                                | Square::from_san("d1").to_bitboard()
-                    };
-
-                    self.white.rooks = self.white.rooks ^ bitmask;
-                }
-            }
+            self.toggle(rook, rook_from.to_bitboard() | rook_to.to_bitboard());
         }
 
         // restore state from the UndoContext
         self.halfmove_clock = undo.halfmove_clock;
         self.en_passant = undo.en_passant;
+        self.castling = undo.castling;
+        self.hash = undo.hash;
 
-        match self.side_to_play {
-            Color::White => { self.fullmove_number -= 1 },
-            _ => {}
+        if self.side_to_play == Color::White {
+            self.fullmove_number -= 1;
         }
 
 
@@ -348,6 +421,96 @@ impl Position {
             Color::White => Color::Black,
             Color::Black => Color::White
         };
+
+        debug_assert_eq!(zobrist::hash(self), self.hash, "hash out of date after undoing {:?}",
+                         motion);
+        debug_assert!(self.occupancy_is_consistent(), "occupancy out of date after undoing {:?}",
+                      motion);
+    }
+
+    /// All moves that follow the rules for how pieces move, without regard to whether they leave
+    /// the side to play in check.
+    pub fn pseudo_legal_moves(&self) -> Vec<Move> {
+        MovesIter::new(self, MagicDatabase::shared()).collect()
+    }
+
+    /// All moves the side to play may actually make.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        MovesIter::legal(self, MagicDatabase::shared()).collect()
+    }
+
+    /// The legal captures and promotions, which are what's left to play once a position is
+    /// quiet. No quiet moves are generated at all.
+    pub fn legal_captures(&self) -> Vec<Move> {
+        let them = self.get_army(self.side_to_play.other()).all;
+        MovesIter::legal(self, MagicDatabase::shared()).with_targets(them).collect()
+    }
+
+    /// The en passant square, but only if a pawn can legally capture there. Otherwise the
+    /// position is no different from one where the last move wasn't a double push.
+    pub fn capturable_en_passant(&self) -> Option<Square> {
+        let en_passant = self.en_passant?;
+
+        // most of the time no pawn is even next to the one that was pushed
+        let capturers = match self.side_to_play {
+            Color::White => movegen::bitmask::black_pawn_attacks(en_passant) & self.white.pawns,
+            Color::Black => movegen::bitmask::white_pawn_attacks(en_passant) & self.black.pawns
+        };
+        if capturers.is_empty() {
+            return None;
+        }
+
+        let mut captures = MovesIter::legal(self, MagicDatabase::shared())
+            .with_targets(en_passant.to_bitboard());
+        if captures.any(|motion| motion.to == en_passant && capturers.is_occupied(motion.from)) {
+            Some(en_passant)
+        } else {
+            None
+        }
+    }
+
+    /// The kind of piece `motion` captures, if it's a capture. This includes en passant.
+    pub fn captured_kind(&self, motion: Move) -> Option<PieceKind> {
+        let pawn = self.get_army(self.side_to_play).pawns.is_occupied(motion.from);
+        if pawn && Some(motion.to) == self.en_passant {
+            return Some(PieceKind::Pawn);
+        }
+
+        self.piece_at(motion.to).map(|piece| piece.kind)
+    }
+
+    /// All pieces, of either color, that attack `square` if the board were occupied by
+    /// `occupied`. Pass `self.all` for the board as it stands; other occupancies let you look
+    /// through pieces, e.g. to find what attacks a square once a piece has moved off it.
+    pub fn attackers_to(&self, square: Square, occupied: Bitboard) -> Bitboard {
+        movegen::attackers_to(self, MagicDatabase::shared(), square, occupied)
+    }
+
+    /// Whether any piece of color `by` attacks `square`.
+    pub fn is_attacked_by(&self, square: Square, by: Color) -> bool {
+        (self.attackers_to(square, self.all) & self.get_army(by).all).is_nonempty()
+    }
+
+    /// The enemy pieces giving check to the side to play.
+    pub fn checkers(&self) -> Bitboard {
+        let us = self.get_army(self.side_to_play);
+        let them = self.get_army(self.side_to_play.other());
+
+        match us.king.squares().next() {
+            Some(king) => self.attackers_to(king, self.all) & them.all,
+            None => Bitboard::new(0)
+        }
+    }
+
+    pub fn is_in_check(&self) -> bool {
+        self.checkers().is_nonempty()
+    }
+
+    pub fn get_army(&self, color: Color) -> &Army {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black
+        }
     }
 
     pub fn get_army_mut(&mut self, color: Color) -> &mut Army {
@@ -361,50 +524,99 @@ impl Position {
         self.get_army_mut(piece.color).get_bitboard_mut(piece.kind)
     }
 
+    /// Adds or removes `piece` on the squares in `bitmask`, keeping the occupancy bitboards in
+    /// step.
+    fn toggle(&mut self, piece: Piece, bitmask: Bitboard) {
+        {
+            let army = self.get_army_mut(piece.color);
+            let bitboard = army.get_bitboard_mut(piece.kind);
+
+            *bitboard = *bitboard ^ bitmask;
+            army.all = army.all ^ bitmask;
+        }
+
+        self.all = self.all ^ bitmask;
+    }
+
     fn update_special_bitboards(&mut self) {
         self.white.update_union();
         self.black.update_union();
         self.all = self.white.all | self.black.all;
     }
+
+    /// Whether the occupancy bitboards match what recomputing them from scratch would give.
+    fn occupancy_is_consistent(&self) -> bool {
+        let mut recomputed = self.clone();
+        recomputed.update_special_bitboards();
+
+        recomputed.white.all == self.white.all
+            && recomputed.black.all == self.black.all
+            && recomputed.all == self.all
+    }
+}
+
+/// When `Position::to_fen_with` should write out the en passant square.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnPassantMode {
+    /// Whenever the last move was a double pawn push, as the FEN standard says.
+    Always,
+    /// Only when the side to play can actually capture en passant. Positions that only differ by
+    /// an en passant square nobody can use then get the same FEN.
+    Legal
 }
 
 pub struct UndoContext {
     pub halfmove_clock: u64,
     pub captured: Option<PieceKind>,
     pub en_passant: Option<Square>,
-    pub reset_oo: bool,
-    pub reset_ooo: bool
+    pub en_passant_capture: bool,
+    pub castling: CastlingRights,
+    pub hash: u64
+}
+
+/// Where the rook starts and ends up when a king on `king` castles.
+fn castling_rook_squares(king: Square, castling: CastlingType) -> (Square, Square) {
+    match castling {
+        CastlingType::Kingside => (king + 3, king + 1),
+        CastlingType::Queenside => (king - 4, king - 1)
+    }
+}
+
+/// The square of the pawn taken by a pawn of color `capturer` moving onto `en_passant`.
+pub fn en_passant_victim(en_passant: Square, capturer: Color) -> Square {
+    match capturer {
+        Color::White => en_passant - 8,
+        Color::Black => en_passant + 8
+    }
 }
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let line = "+---+---+---+---+---+---+---+---+\n";
-        try!(write!(f, "{}", line));
+        write!(f, "{}", line)?;
 
         for rank in (0..8).rev() {
-            try!(write!(f, "|"));
+            write!(f, "|")?;
 
             for file in 0..8 {
                 let sq = Square::from_coords(file, rank);
 
                 match self.piece_at(sq) {
-                    Some(piece) => try!(write!(f, " {} |", piece)),
-                    None => try!(write!(f, "   |"))
+                    Some(piece) => write!(f, " {} |", piece)?,
+                    None => write!(f, "   |")?
                 };
 
             }
 
-            try!(write!(f, "\n"));
-            try!(write!(f, "{}", line));
+            writeln!(f)?;
+            write!(f, "{}", line)?;
         }
 
-        try!(write!(f, "To play: {:?}\n", self.side_to_play));
-        try!(write!(f, "En passant: {:?}\n", self.en_passant));
-        try!(write!(f, "OO: {}, OOO: {}, oo: {}, ooo: {}\n",
-                        self.white_can_oo, self.white_can_ooo,
-                        self.black_can_oo, self.black_can_ooo));
-        try!(write!(f, "Half-move: {}, Full-move: {}\n",
-                        self.halfmove_clock, self.fullmove_number));
+        writeln!(f, "To play: {:?}", self.side_to_play)?;
+        writeln!(f, "En passant: {:?}", self.en_passant)?;
+        writeln!(f, "Castling: {}", self.castling)?;
+        writeln!(f, "Half-move: {}, Full-move: {}",
+                        self.halfmove_clock, self.fullmove_number)?;
 
         Ok(())
     }
@@ -443,6 +655,17 @@ pub struct Army {
 }
 
 impl Army {
+    pub fn get_bitboard(&self, kind: PieceKind) -> Bitboard {
+        match kind {
+            PieceKind::Pawn => self.pawns,
+            PieceKind::Knight => self.knights,
+            PieceKind::Bishop => self.bishops,
+            PieceKind::Rook => self.rooks,
+            PieceKind::Queen => self.queens,
+            PieceKind::King => self.king
+        }
+    }
+
     pub fn get_bitboard_mut(&mut self, kind: PieceKind) -> &mut Bitboard {
         match kind {
             PieceKind::Pawn => &mut self.pawns,
@@ -469,18 +692,28 @@ pub struct Piece {
 impl Piece {
     pub fn new(color: Color, kind: PieceKind) -> Piece {
         Piece {
-            color: color,
-            kind: kind
+            color,
+            kind
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub enum Color {
+    #[default]
     White,
     Black
 }
 
+impl Color {
+    pub fn other(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PieceKind {
     Pawn,
@@ -491,22 +724,13 @@ pub enum PieceKind {
     King
 }
 
-impl Default for Color {
-    fn default() -> Color {
-        Color::White
-    }
-}
-
 #[test]
 fn fen_parsing() {
     let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
     let position = Position::from_fen(fen).unwrap();
 
     assert_eq!(Color::Black, position.side_to_play);
-    assert!(position.white_can_oo);
-    assert!(position.white_can_ooo);
-    assert!(position.black_can_oo);
-    assert!(position.black_can_ooo);
+    assert_eq!(CastlingRights::ALL, position.castling);
     assert_eq!(Some(Square::from_san("e3")), position.en_passant);
     assert_eq!(0, position.halfmove_clock);
     assert_eq!(1, position.fullmove_number);
@@ -610,8 +834,7 @@ fn make_move_castle() {
     assert_eq!(Some(white_king), position.piece_at(Square::from_san("g1")));
     assert_eq!(Some(white_rook), position.piece_at(Square::from_san("f1")));
     assert_eq!(None, position.piece_at(Square::from_san("h1")));
-    assert!(!position.white_can_oo);
-    assert!(!position.white_can_ooo);
+    assert_eq!(CastlingRights::BLACK_OO | CastlingRights::BLACK_OOO, position.castling);
 
     let motion = Move {
         from: Square::from_san("a8"),
@@ -622,8 +845,42 @@ fn make_move_castle() {
 
     position.make_move(motion);
 
-    assert!(position.black_can_oo);
-    assert!(!position.black_can_ooo);
+    assert_eq!(CastlingRights::BLACK_OO, position.castling);
+}
+
+#[test]
+fn make_unmake_castling_rights() {
+    // only black can still castle, so a white king move has no rights to give up
+    let fen = "r3k2r/8/8/8/8/8/8/R3K2R w kq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    let original = position.clone();
+
+    let motion = Move {
+        from: Square::from_san("e1"),
+        to: Square::from_san("f1"),
+        promote_to: None,
+        castling: None
+    };
+
+    let undo = position.make_move(motion);
+    assert_eq!(original.castling, position.castling);
+
+    position.undo_move(motion, undo);
+    assert_eq!(original, position);
+
+    // taking the rook on h8 costs black the right to castle there
+    let motion = Move {
+        from: Square::from_san("h1"),
+        to: Square::from_san("h8"),
+        promote_to: None,
+        castling: None
+    };
+
+    let undo = position.make_move(motion);
+    assert_eq!(CastlingRights::BLACK_OOO, position.castling);
+
+    position.undo_move(motion, undo);
+    assert_eq!(original, position);
 }
 
 #[test]
@@ -732,3 +989,213 @@ fn make_unmake_promotion() {
 
     assert_eq!(original, position);
 }
+
+#[test]
+fn pseudo_legal_and_legal_moves() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    assert_eq!(20, position.pseudo_legal_moves().len());
+    assert_eq!(20, position.legal_moves().len());
+
+    // the d2 knight is pinned, and the king may not step onto the second rank
+    let fen = "4k3/8/8/8/1b6/8/3N3r/4K3 w - - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    let legal_moves = position.legal_moves();
+    assert!(legal_moves.iter().all(|motion| motion.from == Square::from_san("e1")));
+    assert_eq!(2, legal_moves.len());
+}
+
+#[test]
+fn make_unmake_en_passant() {
+    let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+    let mut position = Position::from_fen(fen).unwrap();
+    let original = position.clone();
+
+    let motion = Move {
+        from: Square::from_san("e5"),
+        to: Square::from_san("f6"),
+        promote_to: None,
+        castling: None
+    };
+
+    let undo = position.make_move(motion);
+
+    let white_pawn = Piece::new(Color::White, PieceKind::Pawn);
+    assert_eq!(Some(white_pawn), position.piece_at(Square::from_san("f6")));
+    assert_eq!(None, position.piece_at(Square::from_san("f5")));
+    assert_eq!(None, position.en_passant);
+    assert_eq!(0, position.halfmove_clock);
+
+    position.undo_move(motion, undo);
+    assert_eq!(original, position);
+}
+
+#[test]
+fn make_unmake_keeps_occupancy() {
+    let fen = "r3k2r/6P1/8/8/8/8/8/R3K2R w - - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    let original = position.clone();
+
+    let promotion = Move {
+        from: Square::from_san("g7"),
+        to: Square::from_san("h8"),
+        promote_to: Some(PieceKind::Queen),
+        castling: None
+    };
+
+    let undo = position.make_move(promotion);
+    assert!(position.occupancy_is_consistent());
+    assert_eq!(Bitboard(0x9100000000000091), position.all);
+    assert_eq!(Bitboard(0x1100000000000000), position.black.all);
+
+    position.undo_move(promotion, undo);
+    assert_eq!(original, position);
+
+    let castle = Move {
+        from: Square::from_san("e1"),
+        to: Square::from_san("c1"),
+        promote_to: None,
+        castling: Some(CastlingType::Queenside)
+    };
+
+    let undo = position.make_move(castle);
+    assert!(position.occupancy_is_consistent());
+    assert_eq!(Bitboard(0x0040_0000_0000_008C), position.white.all);
+
+    position.undo_move(castle, undo);
+    assert_eq!(original, position);
+}
+
+#[test]
+fn en_passant_expires() {
+    let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+    let mut position = Position::from_fen(fen).unwrap();
+
+    let motion = Move {
+        from: Square::from_san("g1"),
+        to: Square::from_san("f3"),
+        promote_to: None,
+        castling: None
+    };
+
+    position.make_move(motion);
+    assert_eq!(None, position.en_passant);
+}
+
+#[test]
+fn attack_queries() {
+    let fen = "4k3/8/8/b7/8/2N5/8/R3K3 w - - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    let e1 = Square::from_san("e1");
+    let c3 = Square::from_san("c3");
+    let a5 = Square::from_san("a5");
+
+    // the knight blocks the bishop, but the bishop sees the king once the knight is gone
+    assert!(!position.is_in_check());
+    assert_eq!(Bitboard::new(0), position.checkers());
+    assert!(!position.attackers_to(e1, position.all).is_occupied(a5));
+    assert!(position.attackers_to(e1, position.all ^ c3.to_bitboard()).is_occupied(a5));
+
+    // the rook, knight and king all defend d1
+    let d1 = Square::from_san("d1");
+    let defenders = Square::from_san("a1").to_bitboard() | c3.to_bitboard() | e1.to_bitboard();
+    assert_eq!(defenders, position.attackers_to(d1, position.all));
+    assert!(position.is_attacked_by(d1, Color::White));
+    assert!(!position.is_attacked_by(d1, Color::Black));
+
+    let fen = "4k3/8/8/b7/8/8/8/R3K3 w - - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    assert!(position.is_in_check());
+    assert_eq!(a5.to_bitboard(), position.checkers());
+}
+
+#[test]
+fn fen_round_trip() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "4k3/8/8/8/8/8/8/R3K3 w Q - 37 92"
+    ];
+
+    for fen in fens.iter() {
+        let position = Position::from_fen(fen).unwrap();
+        assert_eq!(*fen, position.to_fen());
+        assert_eq!(position, Position::from_fen(&position.to_fen()).unwrap());
+    }
+}
+
+#[test]
+fn fen_legal_en_passant() {
+    // nothing can take on e3
+    let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    assert_eq!("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+               position.to_fen_with(EnPassantMode::Legal));
+
+    let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+    let position = Position::from_fen(fen).unwrap();
+    assert_eq!(fen, position.to_fen_with(EnPassantMode::Legal));
+
+    // the capture would leave the king in check along the rank
+    let fen = "8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 2";
+    let position = Position::from_fen(fen).unwrap();
+    assert_eq!("8/8/8/K2pP2r/8/8/8/4k3 w - - 0 2", position.to_fen_with(EnPassantMode::Legal));
+}
+
+#[test]
+fn fen_validation() {
+    let invalid_position = |fen: &str| {
+        matches!(Position::from_fen(fen), Err(Error::InvalidPosition(_)))
+    };
+
+    // no white king, two black kings
+    assert!(invalid_position("4k3/8/8/8/8/8/8/8 w - - 0 1"));
+    assert!(invalid_position("3kk3/8/8/8/8/8/8/4K3 w - - 0 1"));
+    // a pawn on the back rank
+    assert!(invalid_position("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"));
+    // black to play with white in check
+    assert!(invalid_position("4k3/8/8/8/8/8/8/r3K3 b - - 0 1"));
+
+    let invalid_field = |fen: &str| match Position::from_fen(fen) {
+        Err(Error::InvalidFen { field, .. }) => Some(field),
+        _ => None
+    };
+
+    assert_eq!(Some(FenField::Castling), invalid_field("4k3/8/8/8/8/8/8/R3K3 w K - 0 1"));
+    assert_eq!(Some(FenField::EnPassant), invalid_field("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"));
+    // the pawn can't have come from e7 if there's still something there
+    assert_eq!(Some(FenField::EnPassant), invalid_field("4k3/4n3/8/4p3/8/8/8/4K3 w - e6 0 1"));
+    assert_eq!(None, invalid_field("4k3/8/8/4p3/8/8/8/4K3 w - e6 0 1"));
+    assert_eq!(Some(FenField::Placement), invalid_field("4k3/8/8/8/8/8/8/4K3X w - - 0 1"));
+}
+
+#[test]
+fn try_make_move() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    let original = position.clone();
+
+    let mut motion = Move {
+        from: Square::from_san("e3"),
+        to: Square::from_san("e4"),
+        promote_to: None,
+        castling: None
+    };
+    let error = position.try_make_move(motion).err();
+    assert_eq!(Some(Error::NoPieceOnSquare(motion.from)), error);
+
+    motion.from = Square::from_san("e1");
+    assert_eq!(Some(Error::IllegalMove(motion)), position.try_make_move(motion).err());
+    assert_eq!(original, position);
+
+    motion.from = Square::from_san("e2");
+    assert!(position.try_make_move(motion).is_ok());
+    assert_eq!(Some(Square::from_san("e3")), position.en_passant);
+}
@@ -0,0 +1,540 @@
+//! Finding the best move: a negamax alpha-beta search, deepened one ply at a time until it runs
+//! out of depth, nodes or time.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use clock::{TimeBudget, TimeManager};
+use eval;
+use motion::Move;
+use ordering::{self, Heuristics, MovePicker};
+use position::Position;
+use transposition::{Bound, Entry, TranspositionTable};
+
+/// The score for delivering mate right now. Mates further away score a little less, so that the
+/// search goes for the quickest one.
+pub const MATE: i32 = 30_000;
+
+/// How deep a search can ever go, which leaves plenty of room between mate scores and any
+/// score the evaluation could come up with.
+pub const MAX_PLY: u32 = 128;
+
+const INFINITY: i32 = MATE + 1;
+
+/// Scores closer to `MATE` than this are mates.
+pub const MATE_THRESHOLD: i32 = MATE - MAX_PLY as i32;
+
+/// How much a capture might gain on top of the piece it takes, for the purposes of deciding
+/// whether it's worth searching in quiescence.
+const DELTA_MARGIN: i32 = 200;
+
+/// When to give up searching. Anything left as `None` is unlimited, and a search that has no
+/// limits at all runs until it's stopped or reaches `MAX_PLY`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// A search with a time budget stops early when it has only one move to choose from, or
+    /// when it's sure enough of its best move.
+    pub time: Option<TimeBudget>
+}
+
+/// A score from the point of view of the side to play.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in this many moves, or getting mated if it's negative.
+    Mate(i32)
+}
+
+/// What the search found on one iteration of deepening.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+    /// How full the transposition table is, in thousandths.
+    pub hashfull: u32,
+    /// The principal variation: the moves both sides are expected to play, best move first.
+    pub pv: Vec<Move>
+}
+
+impl Score {
+    /// Converts a score from the search, where mates are stored as distances from `MATE`.
+    pub fn from_search(score: i32) -> Score {
+        if score >= MATE_THRESHOLD {
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score <= -MATE_THRESHOLD {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Centipawns(score)
+        }
+    }
+}
+
+/// Formats the score the way UCI's "info score" does, like "cp 35" or "mate -2".
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Score::Centipawns(centipawns) => write!(f, "cp {}", centipawns),
+            Score::Mate(moves) => write!(f, "mate {}", moves)
+        }
+    }
+}
+
+impl SearchInfo {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().cloned()
+    }
+}
+
+struct Searcher<'a> {
+    position: Position,
+    limits: SearchLimits,
+    table: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    /// Whether running out of limits can cut the search short, which it can't until there's a
+    /// move to play.
+    can_abort: bool,
+    /// Set once a limit runs out, after which every result is thrown away.
+    aborted: bool,
+    heuristics: Heuristics,
+    /// The hashes of the positions on the way to the one being searched, starting with the ones
+    /// played in the game before the search began, to spot repetitions.
+    path: Vec<u64>,
+    /// The moves on the way to the position being searched.
+    played: Vec<Move>
+}
+
+/// Searches `position` until `limits` run out or `stop` gets set, calling `on_info` after each
+/// iteration with what it found. Returns the result of the last iteration to finish, which is
+/// always at least the first one, so there's a move to play whenever there are legal moves.
+///
+/// `history` has the hashes of the positions played in the game before `position`, oldest
+/// first, so that the search can see repetitions of them too.
+pub fn search<F>(position: &Position, history: &[u64], limits: SearchLimits,
+                 table: &TranspositionTable, stop: &AtomicBool, mut on_info: F) -> SearchInfo
+    where F: FnMut(&SearchInfo) {
+    table.new_search();
+
+    let mut searcher = Searcher {
+        position: position.clone(),
+        limits,
+        table,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        can_abort: false,
+        aborted: false,
+        heuristics: Heuristics::default(),
+        path: history.to_vec(),
+        played: vec![]
+    };
+
+    let max_depth = limits.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
+    let mut best = None;
+
+    let only_move = position.legal_moves().len() == 1;
+    let mut time = limits.time.map(|budget| TimeManager::new(budget, only_move));
+
+    for depth in 1..=max_depth {
+        if searcher.can_abort && searcher.limits_exceeded() {
+            break;
+        }
+
+        let mut pv = vec![];
+        let score = searcher.negamax(depth, 0, -INFINITY, INFINITY, &mut pv);
+
+        // a partial iteration can't be trusted
+        if searcher.aborted {
+            break;
+        }
+
+        extend_pv(position, table, &mut pv, depth);
+
+        let info = SearchInfo {
+            depth,
+            score: Score::from_search(score),
+            nodes: searcher.nodes,
+            time: searcher.start.elapsed(),
+            hashfull: table.hashfull(),
+            pv
+        };
+
+        on_info(&info);
+        searcher.can_abort = true;
+
+        // there's no point looking any deeper once mate has been found, or once another
+        // iteration isn't worth the time
+        let mate_found = score.abs() >= MATE_THRESHOLD;
+        let out_of_time = time.as_mut().is_some_and(|time| {
+            !time.should_continue(&info, searcher.start.elapsed())
+        });
+
+        best = Some(info);
+        if mate_found || out_of_time {
+            break;
+        }
+    }
+
+    best.unwrap()
+}
+
+/// Cutoffs from the transposition table leave the principal variation short, so fill it out to
+/// `depth` moves with the ones the table has for the positions along it.
+fn extend_pv(position: &Position, table: &TranspositionTable, pv: &mut Vec<Move>, depth: u32) {
+    let mut position = position.clone();
+    let mut seen = vec![];
+
+    for &motion in pv.iter() {
+        seen.push(position.hash);
+        position.make_move(motion);
+    }
+
+    while (pv.len() as u32) < depth && !seen.contains(&position.hash) {
+        let motion = match table.probe(position.hash, pv.len() as u32) {
+            Some(Entry { best_move: Some(motion), .. }) => motion,
+            _ => break
+        };
+
+        // a different position with the same hash could have left a move that's illegal here
+        if !position.legal_moves().contains(&motion) {
+            break;
+        }
+
+        seen.push(position.hash);
+        position.make_move(motion);
+        pv.push(motion);
+    }
+}
+
+impl<'a> Searcher<'a> {
+    fn negamax(&mut self, depth: u32, ply: u32, mut alpha: i32, beta: i32, pv: &mut Vec<Move>)
+               -> i32 {
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(ply, alpha, beta);
+        }
+
+        if !self.visit_node() {
+            return 0;
+        }
+
+        if ply > 0 && self.is_draw() {
+            return 0;
+        }
+
+        let mut hash_move = None;
+        if let Some(entry) = self.table.probe(self.position.hash, ply) {
+            hash_move = entry.best_move;
+
+            // the root always gets searched, so that there's a move to play
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha
+            };
+
+            if ply > 0 && entry.depth >= depth && cutoff {
+                pv.extend(hash_move);
+                return entry.score;
+            }
+        }
+
+        let moves = self.position.legal_moves();
+        if moves.is_empty() {
+            return if self.position.is_in_check() { -MATE + ply as i32 } else { 0 };
+        }
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = vec![];
+        let mut quiets_tried = vec![];
+
+        let previous = self.played.last().cloned();
+        let picker = MovePicker::new(&self.position, moves, hash_move, &self.heuristics, ply,
+                                     previous);
+
+        for motion in picker {
+            self.path.push(self.position.hash);
+            self.played.push(motion);
+            let undo = self.position.make_move(motion);
+
+            child_pv.clear();
+            let score = -self.negamax(depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+
+            self.position.undo_move(motion, undo);
+            self.played.pop();
+            self.path.pop();
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(motion);
+
+                if score > alpha {
+                    alpha = score;
+
+                    pv.clear();
+                    pv.push(motion);
+                    pv.extend_from_slice(&child_pv);
+                }
+
+                if score >= beta {
+                    self.heuristics.record_cutoff(&self.position, motion, ply, depth, previous,
+                                                  &quiets_tried);
+                    break;
+                }
+            }
+
+            if ordering::is_quiet(&self.position, motion) {
+                quiets_tried.push(motion);
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            // every move failed low, so there's no telling which one is really best
+            best_move = None;
+            Bound::Upper
+        };
+
+        self.table.store(self.position.hash, ply, depth, bound, best, best_move);
+        best
+    }
+
+    /// Searches captures and promotions until the position is quiet, so that it isn't evaluated
+    /// in the middle of an exchange. The side to play can "stand pat" on the evaluation instead
+    /// of capturing, unless it's in check, in which case every evasion is searched.
+    fn quiescence(&mut self, ply: u32, mut alpha: i32, beta: i32) -> i32 {
+        if !self.visit_node() {
+            return 0;
+        }
+
+        if self.is_draw() {
+            return 0;
+        }
+
+        // mate scores only fit this far from the root, so stop here even in check
+        if ply >= MAX_PLY {
+            return eval::evaluate(&self.position);
+        }
+
+        let in_check = self.position.is_in_check();
+
+        let stand_pat = if in_check { -INFINITY } else { eval::evaluate(&self.position) };
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let moves = if in_check {
+            self.position.legal_moves()
+        } else {
+            self.position.legal_captures()
+        };
+
+        if in_check && moves.is_empty() {
+            return -MATE + ply as i32;
+        }
+
+        let mut best = stand_pat;
+        let picker = MovePicker::new(&self.position, moves, None, &self.heuristics, ply, None);
+
+        for motion in picker {
+            // delta pruning: skip captures that couldn't raise alpha even if they won the piece
+            // for free, with a margin for positional gains
+            if !in_check && motion.promote_to.is_none() {
+                let victim = self.position.captured_kind(motion).unwrap();
+                if stand_pat + eval::piece_value(victim) + DELTA_MARGIN <= alpha {
+                    continue;
+                }
+            }
+
+            // captures that lose material are as good as never played
+            if !in_check && !self.position.see_ge(motion, 0) {
+                continue;
+            }
+
+            self.path.push(self.position.hash);
+            self.played.push(motion);
+            let undo = self.position.make_move(motion);
+
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+
+            self.position.undo_move(motion, undo);
+            self.played.pop();
+            self.path.pop();
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+
+                if score > alpha {
+                    alpha = score;
+                }
+
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Counts a node, and checks whether the search has run out of limits. Returns false once
+    /// the search has been aborted.
+    fn visit_node(&mut self) -> bool {
+        self.nodes += 1;
+
+        // the clock and the stop flag are only checked every so often, since that's slower
+        let out_of_nodes = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+        let check_clock = self.nodes % 1024 == 0;
+        if self.can_abort && (out_of_nodes || (check_clock && self.limits_exceeded())) {
+            self.aborted = true;
+        }
+
+        !self.aborted
+    }
+
+    fn limits_exceeded(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes)
+            || self.limits.time.is_some_and(|time| self.start.elapsed() >= time.hard)
+    }
+
+    /// Draws by the fifty-move rule, lack of material, or repeating a position. A position only
+    /// has to come up a second time to count, since whatever worked once works again.
+    fn is_draw(&self) -> bool {
+        if self.position.halfmove_clock >= 100 || self.position.has_insufficient_material() {
+            return true;
+        }
+
+        // only positions since the last capture or pawn move can possibly repeat
+        let reversible = self.position.halfmove_clock as usize;
+        self.path.iter().rev()
+            .take(reversible)
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == self.position.hash)
+    }
+}
+
+#[cfg(test)]
+fn search_fen(fen: &str, depth: u32) -> SearchInfo {
+    let position = Position::from_fen(fen).unwrap();
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+
+    search(&position, &[], limits, &TranspositionTable::new(1), &AtomicBool::new(false), |_| {})
+}
+
+#[test]
+fn finds_mates() {
+    // back rank mate
+    let info = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 4);
+    assert_eq!(Score::Mate(1), info.score);
+    assert_eq!("a1a8", info.best_move().unwrap().to_uci());
+
+    // the rooks take turns cutting the king off
+    let info = search_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4);
+    assert_eq!(Score::Mate(2), info.score);
+    assert_eq!(3, info.pv.len());
+
+    let info = search_fen("7k/1R6/8/8/8/8/R7/6K1 b - - 0 1", 4);
+    assert_eq!(Score::Mate(-1), info.score);
+    assert_eq!(vec!["h8g8", "a2a8"],
+               info.pv.iter().map(Move::to_uci).collect::<Vec<_>>());
+
+    // stalemate isn't a win
+    let info = search_fen("7k/8/6Q1/8/8/8/8/6K1 b - - 0 1", 3);
+    assert_eq!(Score::Centipawns(0), info.score);
+}
+
+#[test]
+fn wins_material() {
+    // the knight forks king and queen
+    let fen = "4k3/8/8/3q4/4N3/8/7P/4K3 w - - 0 1";
+    let info = search_fen(fen, 3);
+    assert_eq!("e4f6", info.best_move().unwrap().to_uci());
+    match info.score {
+        // a knight for a queen
+        Score::Centipawns(score) => assert!(score > 300),
+        other => panic!("expected a material win, got {:?}", other)
+    }
+
+    let mut depths = vec![];
+    let position = Position::from_fen(fen).unwrap();
+    let limits = SearchLimits {
+        depth: Some(3),
+        ..SearchLimits::default()
+    };
+    let table = TranspositionTable::new(1);
+    let stop = AtomicBool::new(false);
+    search(&position, &[], limits, &table, &stop, |info| depths.push(info.depth));
+    assert_eq!(vec![1, 2, 3], depths);
+}
+
+#[test]
+fn respects_limits() {
+    let position = Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w \
+                                       KQkq - 0 1").unwrap();
+
+    let limits = SearchLimits {
+        nodes: Some(5_000),
+        ..SearchLimits::default()
+    };
+    let table = TranspositionTable::new(1);
+    let info = search(&position, &[], limits, &table, &AtomicBool::new(false), |_| {});
+    assert!(info.best_move().is_some());
+    assert!(info.nodes <= 5_000);
+
+    // stopping straight away still finishes the first iteration
+    let stop = AtomicBool::new(true);
+    let info = search(&position, &[], SearchLimits::default(), &table, &stop, |_| {});
+    assert_eq!(1, info.depth);
+    assert!(info.best_move().is_some());
+}
+
+#[test]
+fn reuses_table() {
+    let position = Position::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq \
+                                       - 2 3").unwrap();
+    let limits = SearchLimits {
+        depth: Some(4),
+        ..SearchLimits::default()
+    };
+
+    let table = TranspositionTable::new(1);
+    let first = search(&position, &[], limits, &table, &AtomicBool::new(false), |_| {});
+    let second = search(&position, &[], limits, &table, &AtomicBool::new(false), |_| {});
+
+    assert_eq!(first.best_move(), second.best_move());
+    assert_eq!(4, second.pv.len());
+    assert!(second.nodes < first.nodes / 2);
+    assert!(first.hashfull > 0);
+}
+
+#[test]
+fn sees_recaptures() {
+    // taking on d5 loses the queen to the pawn on c6, which a search this shallow only sees
+    // by looking at captures past the horizon
+    let info = search_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", 1);
+    assert!(info.best_move().unwrap().to_uci() != "d1d5");
+}
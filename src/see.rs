@@ -0,0 +1,183 @@
+//! Static Exchange Evaluation: working out what a capture wins or loses once every piece that can
+//! join in the exchange on the target square has, without searching any other moves.
+
+use bitboard::Bitboard;
+use magic::MagicDatabase;
+use motion::Move;
+use position::{en_passant_victim, Color, PieceKind, Position};
+use square::Square;
+
+const KINDS_BY_VALUE: [PieceKind; 6] = [PieceKind::Pawn, PieceKind::Knight, PieceKind::Bishop,
+                                        PieceKind::Rook, PieceKind::Queen, PieceKind::King];
+
+impl Position {
+    /// How much material the side to play wins, in centipawns, if both sides keep capturing on
+    /// `motion`'s target square with their least valuable piece for as long as it pays off.
+    /// Either side may stop capturing whenever it likes. Pins are ignored.
+    pub fn see(&self, motion: Move) -> i32 {
+        // castling can't win or lose anything
+        if motion.castling.is_some() {
+            return 0;
+        }
+
+        let magic = MagicDatabase::shared();
+        let to = motion.to;
+
+        let mut occupied = self.all ^ motion.from.to_bitboard();
+        let mover = self.piece_at(motion.from).unwrap().kind;
+
+        if mover == PieceKind::Pawn && Some(to) == self.en_passant {
+            occupied = occupied ^ en_passant_victim(to, self.side_to_play).to_bitboard();
+        }
+
+        let diagonal = self.white.bishops | self.white.queens | self.black.bishops
+            | self.black.queens;
+        let straight = self.white.rooks | self.white.queens | self.black.rooks
+            | self.black.queens;
+
+        // gains[n] is what the side making the nth capture has won if the exchange stops there
+        let mut gains = [0; 32];
+        gains[0] = self.captured_kind(motion).map_or(0, value) + promotion_gain(motion.promote_to);
+
+        let mut on_square = value(motion.promote_to.unwrap_or(mover));
+        let mut side = self.side_to_play.other();
+        let mut attackers = self.attackers_to(to, occupied) & occupied;
+        let mut depth = 0;
+
+        loop {
+            let ours = attackers & self.get_army(side).all;
+            let (kind, from) = match least_valuable(self, side, ours) {
+                Some(attacker) => attacker,
+                None => break
+            };
+
+            // the king can't capture onto a square that's still defended
+            let theirs = attackers & self.get_army(side.other()).all;
+            if kind == PieceKind::King && theirs.is_nonempty() {
+                break;
+            }
+
+            depth += 1;
+            gains[depth] = on_square - gains[depth - 1];
+            on_square = value(kind);
+
+            if kind == PieceKind::Pawn && (to.rank() == 0 || to.rank() == 7) {
+                gains[depth] += promotion_gain(Some(PieceKind::Queen));
+                on_square = value(PieceKind::Queen);
+            }
+
+            // taking the attacker off the board can reveal a slider behind it
+            occupied = occupied ^ from.to_bitboard();
+            attackers = (attackers | (magic.bishop_attacks(to, occupied) & diagonal)
+                         | (magic.rook_attacks(to, occupied) & straight)) & occupied;
+
+            side = side.other();
+        }
+
+        // work back from the end of the exchange, letting each side stop if it's better off
+        while depth > 0 {
+            gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+            depth -= 1;
+        }
+
+        gains[0]
+    }
+
+    /// Whether `see(motion)` is at least `threshold`. This is usually settled without playing
+    /// out the exchange.
+    pub fn see_ge(&self, motion: Move, threshold: i32) -> bool {
+        if motion.castling.is_some() {
+            return threshold <= 0;
+        }
+
+        // the opponent can always decline to recapture, and we can always stop after them, unless
+        // they recapture with a pawn that promotes, which can cost more than the piece we moved
+        let gain = self.captured_kind(motion).map_or(0, value)
+            + promotion_gain(motion.promote_to);
+        let mover = value(motion.promote_to.unwrap_or(self.piece_at(motion.from).unwrap().kind));
+        let promotes = motion.to.rank() == 0 || motion.to.rank() == 7;
+
+        if gain < threshold {
+            false
+        } else if gain - mover >= threshold && !promotes {
+            true
+        } else {
+            self.see(motion) >= threshold
+        }
+    }
+}
+
+/// What a piece is worth in an exchange. The king is never captured, so its value doesn't
+/// matter.
+fn value(kind: PieceKind) -> i32 {
+    match kind {
+        PieceKind::Pawn => 100,
+        PieceKind::Knight | PieceKind::Bishop => 300,
+        PieceKind::Rook => 500,
+        PieceKind::Queen => 900,
+        PieceKind::King => 0
+    }
+}
+
+fn promotion_gain(promote_to: Option<PieceKind>) -> i32 {
+    promote_to.map_or(0, |kind| value(kind) - value(PieceKind::Pawn))
+}
+
+fn least_valuable(position: &Position, side: Color, attackers: Bitboard)
+                  -> Option<(PieceKind, Square)> {
+    let army = position.get_army(side);
+
+    KINDS_BY_VALUE.iter()
+        .filter_map(|&kind| {
+            (army.get_bitboard(kind) & attackers).squares().next().map(|square| (kind, square))
+        })
+        .next()
+}
+
+#[cfg(test)]
+fn see_uci(fen: &str, uci: &str) -> i32 {
+    let position = Position::from_fen(fen).unwrap();
+    position.see(position.parse_uci(uci).unwrap())
+}
+
+#[test]
+fn exchanges() {
+    // an undefended knight
+    assert_eq!(300, see_uci("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"));
+
+    // a pawn defended by a pawn isn't worth a rook
+    assert_eq!(-400, see_uci("4k3/8/2p5/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"));
+
+    // the rook behind joins in once the first one has been taken
+    assert_eq!(100, see_uci("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"));
+    assert_eq!(-400, see_uci("3rk3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"));
+
+    // the king can only recapture when nothing else defends the square
+    assert_eq!(100, see_uci("4r1k1/8/8/8/8/3q4/4P3/4K3 b - - 0 1", "d3e2"));
+    assert_eq!(-800, see_uci("6k1/8/8/8/8/3q4/4P3/4K3 b - - 0 1", "d3e2"));
+
+    // quiet moves can lose material too
+    assert_eq!(-300, see_uci("4k3/8/2p5/8/8/4N3/8/4K3 w - - 0 1", "e3d5"));
+    assert_eq!(0, see_uci("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"));
+}
+
+#[test]
+fn exchange_thresholds() {
+    let position = Position::from_fen("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1").unwrap();
+    let motion = position.parse_uci("d2d5").unwrap();
+
+    assert!(position.see_ge(motion, 0));
+    assert!(position.see_ge(motion, 100));
+    assert!(!position.see_ge(motion, 101));
+
+    // no need to play it out when even losing the rook is good enough
+    assert!(position.see_ge(motion, -400));
+
+    // unless the recapture promotes, and costs more than the bishop
+    let position = Position::from_fen("3Q3k/4P3/8/b7/8/8/8/7K b - - 0 1").unwrap();
+    let motion = position.parse_uci("a5d8").unwrap();
+
+    assert_eq!(-200, position.see(motion));
+    assert!(!position.see_ge(motion, 0));
+    assert!(position.see_ge(motion, -200));
+}
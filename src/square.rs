@@ -1,6 +1,9 @@
+use std::fmt;
 use std::ops::{Add, Sub};
+use std::str::FromStr;
 
 use bitboard::Bitboard;
+use error::{Error, Result};
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub struct Square(u8);
@@ -19,34 +22,26 @@ impl Square {
         Square(file + rank * 8)
     }
 
-    /// Makes a Square from Standard Algebraic Notation (e.g. "a8").
+    /// Parses a square in algebraic notation (e.g. "a8").
+    pub fn parse(san: &str) -> Result<Square> {
+        let bytes = san.as_bytes();
+
+        match *bytes {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Ok(Square::from_coords(file - b'a', rank - b'1'))
+            },
+
+            _ => Err(Error::InvalidSquare(san.to_string()))
+        }
+    }
+
+    /// Makes a Square from Standard Algebraic Notation (e.g. "a8"). Panics if `san` isn't a
+    /// square; use `parse` for input that might not be.
     pub fn from_san(san: &str) -> Square {
-        let san: Vec<_> = san.chars().collect();
-        let file = match san[0] {
-            'a' => 0,
-            'b' => 1,
-            'c' => 2,
-            'd' => 3,
-            'e' => 4,
-            'f' => 5,
-            'g' => 6,
-            'h' => 7,
-            _ => panic!("Unknown file: {:?}", san[0])
-        };
-
-        let rank = match san[1] {
-            '1' => 0,
-            '2' => 1,
-            '3' => 2,
-            '4' => 3,
-            '5' => 4,
-            '6' => 5,
-            '7' => 6,
-            '8' => 7,
-            _ => panic!("Unknown rank: {:?}", san[1])
-        };
-
-        Square::from_coords(file, rank)
+        match Square::parse(san) {
+            Ok(square) => square,
+            Err(error) => panic!("{}", error)
+        }
     }
 
     pub fn to_bitboard(self) -> Bitboard {
@@ -62,6 +57,20 @@ impl Square {
     }
 }
 
+impl FromStr for Square {
+    type Err = Error;
+
+    fn from_str(san: &str) -> Result<Square> {
+        Square::parse(san)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file()) as char, self.rank() + 1)
+    }
+}
+
 impl Add<u8> for Square {
     type Output = Square;
 
@@ -84,3 +93,13 @@ fn san_square_parsing() {
     assert_eq!(Square::new(4 + 2 * 8), Square::from_coords(4, 2));
 }
 
+
+#[test]
+fn fallible_square_parsing() {
+    assert_eq!(Ok(Square::from_coords(0, 7)), "a8".parse());
+    assert_eq!("h1", Square::parse("h1").unwrap().to_string());
+
+    for bad in ["", "e", "e9", "i4", "e44", "E4"].iter() {
+        assert_eq!(Err(Error::InvalidSquare(bad.to_string())), Square::parse(bad));
+    }
+}
@@ -0,0 +1,297 @@
+//! A transposition table: a cache of search results, keyed by Zobrist hash, so that the search
+//! doesn't redo its work when the same position comes up again by a different order of moves.
+
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use motion::Move;
+use search::MATE_THRESHOLD;
+
+/// How a stored score relates to the real score of the position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bound {
+    Exact,
+    /// The real score is at least this much, since the search failed high.
+    Lower,
+    /// The real score is at most this much, since the search failed low.
+    Upper
+}
+
+/// What an earlier search found out about a position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub depth: u32,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<Move>
+}
+
+/// A fixed-size table shared between every thread searching. Nothing is locked; an entry torn by
+/// two threads writing at once just fails to match when it's looked up.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    /// Bumped for every new search, so that entries left over from old ones can be told apart.
+    generation: AtomicU8
+}
+
+/// The entries for positions that land in the same place. The first slot keeps whichever was
+/// searched deepest, and the second takes whatever the first turned down.
+#[derive(Default)]
+struct Bucket {
+    slots: [Slot; 2]
+}
+
+/// An entry, stored with its key XORed with its data so that a torn entry doesn't match.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64
+}
+
+impl TranspositionTable {
+    /// Makes a table that takes up about `megabytes` of memory, and always has room for at least
+    /// one bucket.
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let len = (megabytes * 1024 * 1024 / mem::size_of::<Bucket>()).max(1);
+
+        TranspositionTable {
+            buckets: (0..len).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0)
+        }
+    }
+
+    /// Forgets everything. This mustn't happen while a search is using the table.
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            for slot in &bucket.slots {
+                slot.key.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the start of a new search. Everything stored before this gets replaced first.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Looks up the position with `hash`, which is `ply` moves from the root.
+    pub fn probe(&self, hash: u64, ply: u32) -> Option<Entry> {
+        self.bucket(hash).slots.iter()
+            .filter_map(|slot| slot.load(hash))
+            .next()
+            .map(|data| {
+                let mut entry = data.entry();
+                entry.score = score_from_table(entry.score, ply);
+                entry
+            })
+    }
+
+    /// Stores what the search found out about the position with `hash`, which is `ply` moves
+    /// from the root.
+    pub fn store(&self, hash: u64, ply: u32, depth: u32, bound: Bound, score: i32,
+                 best_move: Option<Move>) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let slots = &self.bucket(hash).slots;
+
+        let mut data = Data::new(depth, bound, score_to_table(score, ply), best_move, generation);
+
+        // the same position goes back where it was, keeping its old move if there's no new one
+        for slot in slots {
+            if let Some(old) = slot.load(hash) {
+                if best_move.is_none() {
+                    data.set_best_move(old.best_move());
+                }
+
+                slot.save(hash, data);
+                return;
+            }
+        }
+
+        let deepest = Data(slots[0].data.load(Ordering::Relaxed));
+        if deepest.is_empty() || deepest.generation() != generation || depth >= deepest.depth() {
+            slots[0].save(hash, data);
+        } else {
+            slots[1].save(hash, data);
+        }
+    }
+
+    /// How full the table is with entries from the current search, in thousandths, estimated
+    /// from the first thousand buckets the way UCI's "info hashfull" wants it.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = &self.buckets[..self.buckets.len().min(1000)];
+
+        let used = sample.iter()
+            .flat_map(|bucket| bucket.slots.iter())
+            .filter(|slot| {
+                let data = Data(slot.data.load(Ordering::Relaxed));
+                !data.is_empty() && data.generation() == generation
+            })
+            .count();
+
+        (used * 1000 / (sample.len() * 2)) as u32
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        // the high bits of the product spread hashes evenly over any number of buckets
+        let index = (hash as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+}
+
+impl Slot {
+    fn load(&self, hash: u64) -> Option<Data> {
+        let key = self.key.load(Ordering::Relaxed);
+        let data = Data(self.data.load(Ordering::Relaxed));
+
+        if !data.is_empty() && key ^ data.0 == hash {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    fn save(&self, hash: u64, data: Data) {
+        self.key.store(hash ^ data.0, Ordering::Relaxed);
+        self.data.store(data.0, Ordering::Relaxed);
+    }
+}
+
+/// An entry packed into 64 bits: 16 for the move, 16 for the score, 8 for the depth, 2 for the
+/// bound and 8 for the generation. A bound of 0 means the slot is empty.
+#[derive(Clone, Copy, Debug)]
+struct Data(u64);
+
+impl Data {
+    fn new(depth: u32, bound: Bound, score: i32, best_move: Option<Move>, generation: u8)
+           -> Data {
+        let bound = match bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3
+        };
+
+        let mut data = Data((score as i16 as u16 as u64) << 16
+                            | (depth.min(255) as u64) << 32
+                            | bound << 40
+                            | (generation as u64) << 48);
+        data.set_best_move(best_move);
+        data
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 >> 40 & 3 == 0
+    }
+
+    fn best_move(self) -> Option<Move> {
+        Move::unpack(self.0 as u16)
+    }
+
+    fn set_best_move(&mut self, best_move: Option<Move>) {
+        let packed = best_move.map_or(0, |motion| motion.pack());
+        self.0 = self.0 & !0xFFFF | packed as u64;
+    }
+
+    fn depth(self) -> u32 {
+        (self.0 >> 32 & 0xFF) as u32
+    }
+
+    fn generation(self) -> u8 {
+        (self.0 >> 48) as u8
+    }
+
+    fn entry(self) -> Entry {
+        let bound = match self.0 >> 40 & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            _ => Bound::Upper
+        };
+
+        Entry {
+            depth: self.depth(),
+            bound,
+            score: (self.0 >> 16) as u16 as i16 as i32,
+            best_move: self.best_move()
+        }
+    }
+}
+
+/// Mate scores count the moves to mate from the root, but a position can come up at any distance
+/// from the root, so the table counts them from the position instead.
+fn score_to_table(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: u32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[test]
+fn store_and_probe() {
+    use position::Position;
+    use search::MATE;
+
+    let table = TranspositionTable::new(1);
+    let position = Position::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").unwrap();
+    let motion = position.parse_uci("a2a7").unwrap();
+
+    assert_eq!(None, table.probe(position.hash, 0));
+    assert_eq!(0, table.hashfull());
+
+    // mate in two from the root is mate in one from two plies in
+    table.store(position.hash, 2, 4, Bound::Exact, MATE - 5, Some(motion));
+    let entry = table.probe(position.hash, 2).unwrap();
+    assert_eq!(Entry { depth: 4, bound: Bound::Exact, score: MATE - 5, best_move: Some(motion) },
+               entry);
+    assert_eq!(MATE - 3, table.probe(position.hash, 0).unwrap().score);
+
+    // a shallower result for the same position replaces it, but keeps the move
+    table.store(position.hash, 0, 1, Bound::Upper, -50, None);
+    let entry = table.probe(position.hash, 0).unwrap();
+    assert_eq!((1, Bound::Upper, -50, Some(motion)),
+               (entry.depth, entry.bound, entry.score, entry.best_move));
+
+    table.clear();
+    assert_eq!(None, table.probe(position.hash, 0));
+}
+
+#[test]
+fn replacement() {
+    // a single bucket, so that everything collides
+    let table = TranspositionTable {
+        buckets: vec![Bucket::default()],
+        generation: AtomicU8::new(0)
+    };
+
+    table.store(1, 0, 8, Bound::Exact, 10, None);
+    table.store(2, 0, 3, Bound::Exact, 20, None);
+    table.store(3, 0, 5, Bound::Exact, 30, None);
+
+    // the deep entry stays, and the shallow ones take turns in the other slot
+    assert_eq!(Some(10), table.probe(1, 0).map(|entry| entry.score));
+    assert_eq!(None, table.probe(2, 0));
+    assert_eq!(Some(30), table.probe(3, 0).map(|entry| entry.score));
+    assert_eq!(1000, table.hashfull());
+
+    // entries from an old search get replaced no matter how deep they were
+    table.new_search();
+    assert_eq!(0, table.hashfull());
+    table.store(4, 0, 1, Bound::Lower, 40, None);
+    assert_eq!(None, table.probe(1, 0));
+    assert_eq!(Some(40), table.probe(4, 0).map(|entry| entry.score));
+}
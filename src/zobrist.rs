@@ -0,0 +1,154 @@
+//! Keys for Zobrist hashing. The keys are generated at compile time from a fixed seed, so a
+//! position hashes to the same value on every run and on every machine.
+
+use castling::CastlingRights;
+use position::{Color, Piece, PieceKind, Position};
+use square::Square;
+
+const PIECE_KEYS: usize = 0;
+const BLACK_TO_PLAY_KEY: usize = PIECE_KEYS + 12 * 64;
+const CASTLING_KEYS: usize = BLACK_TO_PLAY_KEY + 1;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4;
+const NUM_KEYS: usize = EN_PASSANT_KEYS + 8;
+
+const KEYS: [u64; NUM_KEYS] = generate_keys(0x616a_6163_6369_6f21);
+
+/// Fills a table using SplitMix64, which is plenty random for hashing and simple enough to run
+/// in a const fn.
+const fn generate_keys(seed: u64) -> [u64; NUM_KEYS] {
+    let mut keys = [0; NUM_KEYS];
+    let mut state = seed;
+    let mut i = 0;
+
+    while i < NUM_KEYS {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+
+        i += 1;
+    }
+
+    keys
+}
+
+/// The key for `piece` standing on `square`.
+pub fn piece(piece: Piece, square: Square) -> u64 {
+    let color_index = match piece.color {
+        Color::White => 0,
+        Color::Black => 6
+    };
+
+    let kind_index = match piece.kind {
+        PieceKind::Pawn => 0,
+        PieceKind::Knight => 1,
+        PieceKind::Bishop => 2,
+        PieceKind::Rook => 3,
+        PieceKind::Queen => 4,
+        PieceKind::King => 5
+    };
+
+    KEYS[PIECE_KEYS + (color_index + kind_index) * 64 + square.to_index() as usize]
+}
+
+/// The key for whoever is to play. White to play contributes nothing.
+pub fn side_to_play(color: Color) -> u64 {
+    match color {
+        Color::White => 0,
+        Color::Black => KEYS[BLACK_TO_PLAY_KEY]
+    }
+}
+
+/// The combined key for a set of castling rights, one key per right.
+pub fn castling(rights: CastlingRights) -> u64 {
+    (0..4)
+        .filter(|&i| rights.0 & (1 << i) != 0)
+        .fold(0, |hash, i| hash ^ KEYS[CASTLING_KEYS + i])
+}
+
+/// The key for an en passant square, which only depends on its file. Only squares where a
+/// capture is possible should be hashed, so that positions that play the same hash the same.
+pub fn en_passant(en_passant: Option<Square>) -> u64 {
+    match en_passant {
+        Some(square) => KEYS[EN_PASSANT_KEYS + square.file() as usize],
+        None => 0
+    }
+}
+
+/// Hashes `position` from scratch. `Position` keeps its hash up to date as moves are made, so
+/// this is mostly useful for checking that it got that right.
+pub fn hash(position: &Position) -> u64 {
+    let mut hash = side_to_play(position.side_to_play)
+        ^ castling(position.castling)
+        ^ en_passant(position.capturable_en_passant());
+
+    for square in (0..64).map(Square::new) {
+        if let Some(on_square) = position.piece_at(square) {
+            hash ^= piece(on_square, square);
+        }
+    }
+
+    hash
+}
+
+#[test]
+fn keys_are_distinct() {
+    let mut keys = KEYS.to_vec();
+    keys.sort();
+    keys.dedup();
+
+    assert_eq!(NUM_KEYS, keys.len());
+    assert!(keys.iter().all(|&key| key != 0));
+}
+
+#[test]
+fn transpositions_hash_alike() {
+    use motion::Move;
+
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    let original = position.hash;
+
+    let moves = [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")];
+    let mut hashes = vec![];
+    for &(from, to) in moves.iter() {
+        position.make_move(Move {
+            from: Square::from_san(from),
+            to: Square::from_san(to),
+            promote_to: None,
+            castling: None
+        });
+
+        hashes.push(position.hash);
+    }
+
+    // back where we started, but with different move counters
+    assert_eq!(original, position.hash);
+    assert_eq!(hash(&position), position.hash);
+
+    hashes.sort();
+    hashes.dedup();
+    assert_eq!(4, hashes.len());
+
+    // the same pieces with the other side to play
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+    assert!(original != Position::from_fen(fen).unwrap().hash);
+}
+
+#[test]
+fn en_passant_only_counts_when_capturable() {
+    // after 1. e4 there's nothing to take on e3, so it's as if the pawn had walked there
+    let pushed = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+        .unwrap();
+    let walked = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        .unwrap();
+    assert_eq!(walked.hash, pushed.hash);
+
+    // but a black pawn on d4 could take it
+    let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+    let capturable = Position::from_fen(fen).unwrap();
+    let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3";
+    assert!(capturable.hash != Position::from_fen(fen).unwrap().hash);
+}